@@ -0,0 +1,1953 @@
+// src/app.rs
+
+use crate::{
+    editor::TextEditor,
+    keymap::{self, Command},
+};
+use chrono::{Local, NaiveDate};
+use crossterm::event::{KeyCode, KeyEvent, MouseButton, MouseEvent, MouseEventKind};
+use ratatui::{
+    backend::Backend,
+    layout::{Alignment, Constraint, Direction, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::{Span, Spans},
+    widgets::{Block, Borders, Clear, Paragraph, Sparkline, Wrap},
+    Frame,
+};
+use ratodui::{
+    build_progress_bar, formats, index_of, is_blocked, merge, next_occurrence, parse_blockers,
+    push_todo, record_progress, recurrence::Recurrence, unblocked_by, ProgressEntry, Todo,
+};
+use std::time::{Duration, Instant, SystemTime};
+
+// How long changes have to settle before they are written, so a drag
+// saves once instead of on every step
+pub const SAVE_DELAY: Duration = Duration::from_millis(500);
+
+// Input the app reacts to
+pub enum Action {
+    Key(KeyEvent),
+    Mouse(MouseEvent),
+    Resize(u16, u16), // The terminal's new width and height
+}
+
+// Things an update asks the event loop to do, as they need the terminal or
+// the file system
+#[derive(Debug, PartialEq)]
+pub enum Effect {
+    Quit,
+    Suspend,             // Stop until the shell continues us, as Ctrl+Z does
+    EditExternally(u64), // Edit the todo with this ID in $EDITOR
+    RunCommand(String),  // Run a command typed after ':'
+}
+
+// Single-line fields that can be edited from the details popup
+#[derive(Clone, Copy, PartialEq)]
+pub enum DetailsField {
+    Due,
+    Recurrence,
+    BlockedBy,
+    Priority,
+    Tags,
+}
+
+// Todos read by ':import', shown for confirmation before they are merged
+pub struct ImportPreview {
+    pub source: String,
+    pub todos: Vec<Todo>,
+    pub scroll: usize,
+}
+
+// Changes another program made to the data file, merged with ours and
+// waiting for the conflicts to be resolved
+pub struct Reload {
+    pub merged: merge::Merged,
+    pub theirs: Vec<Todo>,
+}
+
+// What the keys are doing, as shown in the status bar
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Mode {
+    Normal,
+    Edit,    // Typing a name, the notes or a field of the details popup
+    Command, // Typing a command after ':'
+}
+
+impl Mode {
+    pub fn label(self) -> &'static str {
+        match self {
+            Mode::Normal => "NORMAL",
+            Mode::Edit => "EDIT",
+            Mode::Command => "COMMAND",
+        }
+    }
+}
+
+// Everything the TUI shows and edits. Input goes through `update`, which
+// leaves reading and writing files to the event loop, and `view` draws it.
+pub struct App {
+    pub todos: Vec<Todo>,
+    pub area: Rect, // Size of the terminal, to tell what the mouse points at
+
+    // The todos as the data file had them, to merge changes other programs
+    // make to it
+    pub base: Vec<Todo>,
+    pub file_stamp: Option<(SystemTime, u64)>,
+    pub reload: Option<Reload>,
+    pub read_only: bool,   // Another ratodui is editing the todos
+    pub file_name: String, // Shown in the status bar
+
+    // Set to the time of the last change that hasn't been saved yet
+    pub unsaved: Option<Instant>,
+    save_now: bool, // The last change is saved without waiting for SAVE_DELAY
+
+    // Variables for mouse interaction
+    dragging: bool,
+    drag_id: Option<u64>,
+
+    // Variables for editing todo names
+    editing_id: Option<u64>,
+    input_buffer: String,
+    just_started_editing: bool, // Flag to indicate if we just entered edit mode
+
+    // Variables for keyboard navigation and the details popup
+    selected: usize,
+    scroll: usize, // How many todos are scrolled above the top of the list
+    details_id: Option<u64>,
+    notes_editor: Option<TextEditor>, // Set while editing notes in the popup
+    details_input: Option<(DetailsField, String)>, // Set while editing a field
+
+    // Variables for the command line and its result
+    command_input: Option<String>,
+    pub status_message: Option<String>,
+    pub import_preview: Option<ImportPreview>,
+
+    // Set to how far the help popup is scrolled while it is open
+    help_scroll: Option<usize>,
+}
+
+impl App {
+    pub fn new(todos: Vec<Todo>, area: Rect, read_only: bool) -> Self {
+        App {
+            base: todos.clone(),
+            todos,
+            area,
+            file_stamp: None,
+            reload: None,
+            read_only,
+            file_name: String::new(),
+            unsaved: None,
+            save_now: false,
+            dragging: false,
+            drag_id: None,
+            editing_id: None,
+            input_buffer: String::new(),
+            just_started_editing: false,
+            selected: 0,
+            scroll: 0,
+            details_id: None,
+            notes_editor: None,
+            details_input: None,
+            command_input: None,
+            status_message: None,
+            import_preview: None,
+            help_scroll: None,
+        }
+    }
+
+    // Function to react to a key press or mouse event. Returns what the
+    // event loop has to do about it, if anything.
+    pub fn update(&mut self, action: Action) -> Option<Effect> {
+        self.forget_missing();
+
+        // Raw mode keeps the terminal from turning Ctrl+Z into SIGTSTP
+        if let Action::Key(key_event) = action {
+            if keymap::lookup(keymap::GLOBAL, key_event) == Some(Command::Suspend) {
+                return Some(Effect::Suspend);
+            }
+        }
+        // Lay out for the new size right away, whatever we are doing
+        if let Action::Resize(width, height) = action {
+            self.resize(width, height);
+            return None;
+        }
+
+        // A read-only view can only be looked at
+        if self.read_only && !self.is_viewing_action(&action) {
+            self.status_message = Some(String::from(
+                "Read-only: another ratodui is editing these todos",
+            ));
+            return None;
+        }
+        // Todos are tracked by ID; look up where they currently are
+        let details_index = self.details_id.and_then(|id| index_of(&self.todos, id));
+        let editing_index = self.editing_id.and_then(|id| index_of(&self.todos, id));
+
+        match action {
+            // We are showing the help
+            Action::Key(key_event) if self.help_scroll.is_some() => self.update_help(key_event),
+            Action::Mouse(mouse_event) if self.help_scroll.is_some() => {
+                // Any click closes the popup
+                if let MouseEventKind::Down(_) = mouse_event.kind {
+                    self.help_scroll = None;
+                }
+            }
+            // We are asking which version of a todo changed on both sides to keep
+            Action::Key(key_event) if self.reload.is_some() => self.resolve_conflict(key_event),
+            _ if self.reload.is_some() => {}
+            // We are looking at the todos an import would add
+            Action::Key(key_event) if self.import_preview.is_some() => {
+                self.update_import_preview(key_event);
+            }
+            _ if self.import_preview.is_some() => {}
+            // We are typing a command
+            Action::Key(key_event) if self.command_input.is_some() => {
+                return self.update_command(key_event);
+            }
+            _ if self.command_input.is_some() => {}
+            _ => match (details_index, editing_index) {
+                (Some(i), _) => return self.update_details(i, action),
+                (None, Some(i)) => return self.update_editing(i, action),
+                (None, None) => return self.update_list(action),
+            },
+        }
+        None
+    }
+
+    // Function to tell whether an action only looks at the todos, which is
+    // all a read-only view allows. Nothing can be typed in it, so only the
+    // list, the details and the help take keys.
+    fn is_viewing_action(&self, action: &Action) -> bool {
+        match action {
+            Action::Key(key_event) => {
+                let bindings = if self.help_scroll.is_some() {
+                    keymap::HELP
+                } else if self.details_id.is_some() {
+                    keymap::DETAILS
+                } else {
+                    keymap::LIST
+                };
+                keymap::lookup(bindings, *key_event)
+                    .is_some_and(|command| keymap::READ_ONLY.contains(&command))
+            }
+            Action::Mouse(_) => false,
+            Action::Resize(..) => true,
+        }
+    }
+
+    // Function to keep a title or notes being typed when the app ends before
+    // they are finished, e.g. on a signal
+    pub fn finish_pending_edits(&mut self) {
+        if let Some(i) = self.editing_id.and_then(|id| index_of(&self.todos, id)) {
+            self.finish_editing(i);
+        }
+        let details_index = self.details_id.and_then(|id| index_of(&self.todos, id));
+        if let (Some(i), Some(editor)) = (details_index, self.notes_editor.take()) {
+            self.todos[i].notes = editor.text().to_string();
+            self.changed();
+        }
+    }
+
+    // Function to tell what the keys are doing
+    pub fn mode(&self) -> Mode {
+        if self.command_input.is_some() {
+            Mode::Command
+        } else if self.editing_id.is_some()
+            || self.notes_editor.is_some()
+            || self.details_input.is_some()
+        {
+            Mode::Edit
+        } else {
+            Mode::Normal
+        }
+    }
+
+    // Function to tell whether the changes should be written now: right after
+    // a click or key press changed something, or once the steps of a drag have
+    // settled. During a conflict they are saved with its resolution.
+    pub fn needs_save(&mut self) -> bool {
+        let save_now = std::mem::take(&mut self.save_now);
+        let settled = self
+            .unsaved
+            .is_some_and(|since| since.elapsed() >= SAVE_DELAY);
+        self.unsaved.is_some() && (save_now || settled) && self.reload.is_none()
+    }
+
+    // Function to note a change that is saved right away
+    pub fn changed(&mut self) {
+        self.unsaved = Some(Instant::now());
+        self.save_now = true;
+    }
+
+    // Function to start asking about changes another program made that
+    // conflict with ours
+    pub fn start_reload(&mut self, changes: Reload) {
+        // The conflicts have to be resolved first; a drag ends here
+        self.dragging = false;
+        self.drag_id = None;
+        self.reload = Some(changes);
+    }
+
+    // Function to take over merged changes, saving the result if it differs
+    // from what is in the file
+    pub fn finish_reload(&mut self, changes: Reload) {
+        let Reload { merged, theirs } = changes;
+        // Until the merge is saved the file still holds their version
+        if merged.todos != theirs {
+            self.changed();
+        }
+        self.base = theirs;
+        self.todos = merged.todos;
+        self.forget_missing();
+    }
+
+    // Function to take a new terminal size, keeping the selected todo and the
+    // import preview within what fits on the screen
+    fn resize(&mut self, width: u16, height: u16) {
+        self.area = Rect::new(0, 0, width, height);
+        self.scroll_to_selection();
+        if let Some(preview) = self.import_preview.as_mut() {
+            let rows = Block::default()
+                .borders(Borders::ALL)
+                .inner(import_preview_area(self.area))
+                .height as usize;
+            preview.scroll = preview.scroll.min(preview.todos.len().saturating_sub(rows));
+        }
+    }
+
+    // Function to forget about todos that went away with a reload
+    fn forget_missing(&mut self) {
+        self.details_id = self
+            .details_id
+            .filter(|id| index_of(&self.todos, *id).is_some());
+        self.editing_id = self
+            .editing_id
+            .filter(|id| index_of(&self.todos, *id).is_some());
+        if self.details_id.is_none() {
+            self.notes_editor = None;
+            self.details_input = None;
+        }
+        self.selected = self.selected.min(self.todos.len().saturating_sub(1));
+        self.scroll_to_selection();
+    }
+
+    // Function to scroll the list as little as needed to show the selected
+    // todo, and the add button along with the last todo
+    fn scroll_to_selection(&mut self) {
+        let rows = list_rows(self.area);
+        // The todos and the add button, one row each
+        let entries = self.todos.len() + 1;
+        let end = if self.selected + 1 == self.todos.len() {
+            entries
+        } else {
+            self.selected + 1
+        };
+        self.scroll = self
+            .scroll
+            .min(self.selected)
+            .max(end.saturating_sub(rows))
+            .min(entries.saturating_sub(rows));
+    }
+
+    fn resolve_conflict(&mut self, key_event: KeyEvent) {
+        let Some(changes) = self.reload.as_mut() else {
+            return;
+        };
+        let choice = match keymap::lookup(keymap::CONFLICT, key_event) {
+            Some(Command::KeepMine) => Some((false, 1)),
+            Some(Command::TakeTheirs) => Some((true, 1)),
+            Some(Command::KeepAllMine) => Some((false, changes.merged.conflicts.len())),
+            Some(Command::TakeAllTheirs) => Some((true, changes.merged.conflicts.len())),
+            _ => None,
+        };
+        if let Some((take_theirs, count)) = choice {
+            for conflict in changes.merged.conflicts.drain(..count) {
+                merge::resolve(&mut changes.merged.todos, conflict, take_theirs);
+            }
+            if changes.merged.conflicts.is_empty() {
+                let changes = self.reload.take().unwrap();
+                self.finish_reload(changes);
+                self.status_message = Some(String::from("Merged the changes of the other program"));
+            }
+        }
+    }
+
+    fn update_import_preview(&mut self, key_event: KeyEvent) {
+        let Some(preview) = self.import_preview.as_mut() else {
+            return;
+        };
+        match keymap::lookup(keymap::IMPORT_PREVIEW, key_event) {
+            Some(Command::Up) => {
+                preview.scroll = preview.scroll.saturating_sub(1);
+            }
+            Some(Command::Down) if preview.scroll + 1 < preview.todos.len() => {
+                preview.scroll += 1;
+            }
+            Some(Command::Confirm) => {
+                let preview = self.import_preview.take().unwrap();
+                let count = formats::merge(&mut self.todos, preview.todos);
+                self.status_message =
+                    Some(format!("Imported {} todos from {}", count, preview.source));
+                self.changed();
+            }
+            Some(Command::Cancel) => {
+                self.import_preview = None;
+                self.status_message = Some(String::from("Import cancelled"));
+            }
+            _ => {}
+        }
+    }
+
+    fn update_command(&mut self, key_event: KeyEvent) -> Option<Effect> {
+        let command = self.command_input.as_mut()?;
+        match keymap::lookup(keymap::COMMAND, key_event) {
+            Some(Command::Confirm) => return self.command_input.take().map(Effect::RunCommand),
+            Some(_) => self.command_input = None,
+            None => match key_event.code {
+                KeyCode::Char(c) => command.push(c),
+                KeyCode::Backspace => {
+                    command.pop();
+                }
+                _ => {}
+            },
+        }
+        None
+    }
+
+    fn update_help(&mut self, key_event: KeyEvent) {
+        let Some(scroll) = self.help_scroll.as_mut() else {
+            return;
+        };
+        match keymap::lookup(keymap::HELP, key_event) {
+            Some(Command::Up) => *scroll = scroll.saturating_sub(1),
+            Some(Command::Down) if *scroll + 1 < help_lines(self.details_id.is_some()).len() => {
+                *scroll += 1;
+            }
+            Some(Command::Close) => self.help_scroll = None,
+            _ => {}
+        }
+    }
+
+    // Function to handle input while the details popup of the todo at `i` is open
+    fn update_details(&mut self, i: usize, action: Action) -> Option<Effect> {
+        if let Some(editor) = self.notes_editor.as_mut() {
+            // We are editing the notes in the details popup
+            if let Action::Key(key_event) = action {
+                if keymap::lookup(keymap::EDIT_NOTES, key_event).is_some() {
+                    // Store the notes and go back to the details view
+                    self.todos[i].notes = editor.text().to_string();
+                    self.notes_editor = None;
+                    self.changed();
+                    return None;
+                }
+                match key_event.code {
+                    KeyCode::Char(c) => editor.insert(c),
+                    KeyCode::Enter => editor.insert('\n'),
+                    KeyCode::Tab => editor.insert('\t'),
+                    KeyCode::Backspace => editor.backspace(),
+                    KeyCode::Delete => editor.delete(),
+                    KeyCode::Left => editor.move_left(),
+                    KeyCode::Right => editor.move_right(),
+                    KeyCode::Up => editor.move_up(),
+                    KeyCode::Down => editor.move_down(),
+                    KeyCode::Home => editor.move_home(),
+                    KeyCode::End => editor.move_end(),
+                    _ => {}
+                }
+            }
+        } else if let Some((field, buffer)) = self.details_input.as_mut() {
+            // We are editing the due date or recurrence in the details popup
+            if let Action::Key(key_event) = action {
+                match keymap::lookup(keymap::EDIT_FIELD, key_event) {
+                    Some(Command::Confirm) => {
+                        let (field, value) = (*field, buffer.trim().to_string());
+                        self.set_details_field(i, field, &value);
+                        self.details_input = None;
+                        self.changed();
+                    }
+                    Some(_) => self.details_input = None,
+                    None => match key_event.code {
+                        KeyCode::Char(c) => buffer.push(c),
+                        KeyCode::Backspace => {
+                            buffer.pop();
+                        }
+                        _ => {}
+                    },
+                }
+            }
+        } else {
+            // The details popup is open
+            let todo = &self.todos[i];
+            match action {
+                Action::Key(key_event) => match keymap::lookup(keymap::DETAILS, key_event) {
+                    Some(Command::Quit) => return Some(Effect::Quit),
+                    Some(Command::Help) => self.help_scroll = Some(0),
+                    Some(Command::EditDue) => {
+                        let due = todo.due.map(|due| due.to_string());
+                        self.details_input = Some((DetailsField::Due, due.unwrap_or_default()));
+                    }
+                    Some(Command::EditRecurrence) => {
+                        let recurrence = todo.recurrence.as_ref().map(|r| r.to_string());
+                        self.details_input =
+                            Some((DetailsField::Recurrence, recurrence.unwrap_or_default()));
+                    }
+                    Some(Command::EditPriority) => {
+                        let priority = todo.priority.map(String::from);
+                        self.details_input =
+                            Some((DetailsField::Priority, priority.unwrap_or_default()));
+                    }
+                    Some(Command::EditTags) => {
+                        let tags = todo.tags.join(" ");
+                        self.details_input = Some((DetailsField::Tags, tags));
+                    }
+                    Some(Command::EditBlockers) => {
+                        let ids: Vec<String> =
+                            todo.blocked_by.iter().map(|id| id.to_string()).collect();
+                        self.details_input = Some((DetailsField::BlockedBy, ids.join(", ")));
+                    }
+                    Some(Command::EditNotes) => {
+                        // Start editing the notes
+                        self.notes_editor = Some(TextEditor::new(&todo.notes));
+                    }
+                    // Edit the name and notes in $EDITOR
+                    Some(Command::EditExternally) => return Some(Effect::EditExternally(todo.id)),
+                    Some(Command::Close) => self.details_id = None,
+                    _ => {}
+                },
+                Action::Mouse(mouse_event) => {
+                    // Any click closes the popup
+                    if let MouseEventKind::Down(_) = mouse_event.kind {
+                        self.details_id = None;
+                    }
+                }
+                Action::Resize(..) => {} // Handled in update
+            }
+        }
+        None
+    }
+
+    // Function to store a field typed in the details popup. Invalid input
+    // leaves the field unchanged.
+    fn set_details_field(&mut self, i: usize, field: DetailsField, value: &str) {
+        match field {
+            DetailsField::Due if value.is_empty() => self.todos[i].due = None,
+            DetailsField::Due => {
+                if let Ok(due) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+                    self.todos[i].due = Some(due);
+                }
+            }
+            DetailsField::Recurrence if value.is_empty() => {
+                self.todos[i].recurrence = None;
+            }
+            DetailsField::Recurrence => {
+                if let Some(recurrence) = Recurrence::parse(value) {
+                    self.todos[i].recurrence = Some(recurrence);
+                }
+            }
+            DetailsField::BlockedBy => {
+                self.todos[i].blocked_by = parse_blockers(value, &self.todos, i);
+            }
+            DetailsField::Priority => {
+                let priority = value.to_ascii_uppercase();
+                let mut chars = priority.chars();
+                match (chars.next(), chars.next()) {
+                    (None, _) => self.todos[i].priority = None,
+                    (Some(c), None) if c.is_ascii_uppercase() => {
+                        self.todos[i].priority = Some(c);
+                    }
+                    _ => {}
+                }
+            }
+            DetailsField::Tags => {
+                self.todos[i].tags = value.split_whitespace().map(String::from).collect();
+            }
+        }
+    }
+
+    // Function to handle input while the name of the todo at `i` is edited
+    fn update_editing(&mut self, i: usize, action: Action) -> Option<Effect> {
+        match action {
+            Action::Key(key_event) => match keymap::lookup(keymap::EDIT_NAME, key_event) {
+                // Update the todo's name and exit edit mode
+                Some(_) => self.finish_editing(i),
+                None => match key_event.code {
+                    KeyCode::Char(c) => self.input_buffer.push(c),
+                    KeyCode::Backspace => {
+                        self.input_buffer.pop();
+                    }
+                    // Any other key press exits edit mode and saves the name
+                    _ => self.finish_editing(i),
+                },
+            },
+            Action::Mouse(mouse_event) => {
+                if self.just_started_editing {
+                    // Ignore the mouse event that initiated edit mode
+                    self.just_started_editing = false;
+                } else if mouse_event.kind != MouseEventKind::Moved {
+                    // Other mouse events exit edit mode, and then act as usual
+                    self.finish_editing(i);
+                    self.process_mouse_event(mouse_event);
+                }
+            }
+            Action::Resize(..) => {} // Handled in update
+        }
+        None
+    }
+
+    // Function to store the edited name and leave edit mode
+    fn finish_editing(&mut self, i: usize) {
+        self.todos[i].name = std::mem::take(&mut self.input_buffer);
+        self.editing_id = None;
+        // Save the todos after renaming
+        self.changed();
+    }
+
+    // Function to handle input while the list itself has the focus
+    fn update_list(&mut self, action: Action) -> Option<Effect> {
+        match action {
+            Action::Key(key_event) => match keymap::lookup(keymap::LIST, key_event) {
+                Some(Command::Quit) => return Some(Effect::Quit),
+                Some(Command::Help) => self.help_scroll = Some(0),
+                Some(Command::OpenCommandLine) => self.command_input = Some(String::new()),
+                Some(Command::Up) => {
+                    self.selected = self.selected.saturating_sub(1);
+                    self.scroll_to_selection();
+                }
+                Some(Command::Down) if self.selected + 1 < self.todos.len() => {
+                    self.selected += 1;
+                    self.scroll_to_selection();
+                }
+                Some(Command::OpenDetails) if self.selected < self.todos.len() => {
+                    // Open the details popup for the selected todo
+                    self.details_id = Some(self.todos[self.selected].id);
+                }
+                Some(Command::EditExternally) if self.selected < self.todos.len() => {
+                    // Edit the selected todo's name and notes in $EDITOR
+                    return Some(Effect::EditExternally(self.todos[self.selected].id));
+                }
+                _ => {}
+            },
+            Action::Mouse(mouse_event) => self.process_mouse_event(mouse_event),
+            Action::Resize(..) => {} // Handled in update
+        }
+        None
+    }
+
+    // Function to process mouse events on the list
+    fn process_mouse_event(&mut self, mouse_event: MouseEvent) {
+        let chunks = compute_chunks(self.area, &self.todos, self.scroll);
+        let todos = &mut self.todos;
+        match mouse_event.kind {
+            MouseEventKind::Down(MouseButton::Right) => {
+                // Right-clicking a todo opens its details popup
+                let mouse_pos = (mouse_event.column, mouse_event.row);
+                self.details_id = chunks
+                    .iter()
+                    .zip(todos.iter())
+                    .find(|(chunk, _)| is_inside(mouse_pos, **chunk))
+                    .map(|(_, todo)| todo.id);
+            }
+            MouseEventKind::Down(MouseButton::Left) => {
+                // Get the mouse position
+                let mouse_pos = (mouse_event.column, mouse_event.row);
+                let mut clicked_on_todo = false;
+                // Check if click is on any todo item
+                for (i, chunk) in chunks.iter().enumerate() {
+                    if i >= todos.len() {
+                        break;
+                    }
+                    if is_inside(mouse_pos, *chunk) {
+                        clicked_on_todo = true;
+
+                        // Split the line into title and progress bar
+                        let horizontal_chunks = split_line(*chunk);
+
+                        // Further split the first chunk into checkbox and title
+                        let checkbox_and_title_chunks = Layout::default()
+                            .direction(Direction::Horizontal)
+                            .constraints(
+                                [
+                                    Constraint::Length(4), // For '[ ] '
+                                    Constraint::Min(1),    // Remaining space for title
+                                ]
+                                .as_ref(),
+                            )
+                            .split(horizontal_chunks[0]);
+
+                        if is_inside(mouse_pos, checkbox_and_title_chunks[0]) {
+                            // Clicked on the checkbox - toggle completed status
+                            if !todos[i].completed && is_blocked(&todos[i], todos) {
+                                // Blocked todos can't be completed until their blockers are
+                                let blockers: Vec<String> = open_blockers(&todos[i], todos)
+                                    .iter()
+                                    .map(|todo| format!("#{} {}", todo.id, todo.name))
+                                    .collect();
+                                self.status_message =
+                                    Some(format!("Blocked by {}", blockers.join(", ")));
+                                return;
+                            }
+                            let completed = !todos[i].completed;
+                            todos[i].set_completed(completed);
+                            if todos[i].completed {
+                                // Completing a recurring todo spawns its next occurrence
+                                let today = Local::now().date_naive();
+                                if let Some(next) = next_occurrence(&mut todos[i], today) {
+                                    push_todo(todos, next);
+                                }
+                            }
+                            // Save the todos
+                            self.changed();
+                            return; // Exit function, no further processing needed
+                        } else if is_inside(mouse_pos, checkbox_and_title_chunks[1]) {
+                            // Clicked on the title area - start editing
+                            self.editing_id = Some(todos[i].id);
+                            self.just_started_editing = true; // Indicate that we just entered edit mode
+                            if todos[i].name == "New Todo" {
+                                self.input_buffer = String::new(); // Start with an empty input buffer
+                            } else {
+                                self.input_buffer = todos[i].name.clone(); // Start with the existing name
+                            }
+                        } else if is_inside(mouse_pos, horizontal_chunks[1]) {
+                            // Clicked on the progress bar area
+                            // Start dragging to update progress
+                            self.dragging = true;
+                            self.drag_id = Some(todos[i].id);
+                            update_progress(
+                                &mut todos[i],
+                                horizontal_chunks[1],
+                                mouse_event.column,
+                            );
+                            // Saved once the drag ends or settles
+                            self.unsaved = Some(Instant::now());
+                        }
+
+                        break; // We've found the clicked todo, so we can exit the loop
+                    }
+                }
+                // Check if click is on the add button
+                if !clicked_on_todo {
+                    if let Some(add_button_rect) = chunks.get(todos.len()) {
+                        if is_inside(mouse_pos, *add_button_rect) {
+                            // Add a new todo
+                            push_todo(todos, Todo::new("New Todo"));
+                            // Save the todos after adding a new one
+                            self.changed();
+                        }
+                    }
+                }
+            }
+            MouseEventKind::Drag(MouseButton::Left)
+                if self.editing_id.is_none() && self.dragging =>
+            {
+                if let Some(i) = self.drag_id.and_then(|id| index_of(todos, id)) {
+                    let horizontal_chunks = split_line(chunks[i]);
+                    update_progress(&mut todos[i], horizontal_chunks[1], mouse_event.column);
+                    // Drags report many positions a second, so only note that there
+                    // is something to save
+                    self.unsaved = Some(Instant::now());
+                }
+            }
+            MouseEventKind::Up(MouseButton::Left) => {
+                self.dragging = false;
+                // The drag gesture is over, so record it as a single history entry
+                if let Some(i) = self.drag_id.take().and_then(|id| index_of(todos, id)) {
+                    record_progress(&mut todos[i]);
+                    self.changed();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Function to render the UI
+    pub fn view<B: Backend>(&self, f: &mut Frame<B>) {
+        let todos = &self.todos;
+        let chunks = compute_chunks(f.size(), todos, self.scroll);
+
+        for (i, todo) in todos.iter().enumerate() {
+            let mut style = Style::default();
+            let mut title: String;
+            let checkbox = if todo.completed { "[x]" } else { "[ ]" };
+
+            if self.editing_id == Some(todo.id) {
+                // Render input buffer with a cursor
+                title = format!("{}_", self.input_buffer); // Add cursor
+                style = Style::default().fg(Color::Yellow);
+            } else {
+                title = match todo.priority {
+                    Some(priority) => format!("({}) {}", priority, todo.name),
+                    None => todo.name.clone(),
+                };
+                if todo.recurrence.is_some() {
+                    title.push_str(" \u{21bb}"); // Indicate that the todo repeats
+                }
+                if !todo.notes.is_empty() {
+                    title.push_str(" \u{2261}"); // Indicate that the todo has notes
+                }
+                if is_blocked(todo, todos) {
+                    title.push_str(" \u{2298}"); // Indicate that the todo is blocked
+                    style = style.fg(Color::DarkGray);
+                }
+                if i == self.selected {
+                    style = style.add_modifier(Modifier::BOLD);
+                }
+            }
+
+            let area = chunks[i];
+
+            // Inside each chunk (line), create a horizontal layout
+            let horizontal_chunks = split_line(area);
+
+            // Further split the first chunk into checkbox and title
+            let checkbox_and_title_chunks = Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints(
+                    [
+                        Constraint::Length(4), // For '[ ] '
+                        Constraint::Min(1),    // Remaining space for title
+                    ]
+                    .as_ref(),
+                )
+                .split(horizontal_chunks[0]);
+
+            // Render the checkbox
+            let checkbox_paragraph = Paragraph::new(Span::raw(checkbox));
+            f.render_widget(checkbox_paragraph, checkbox_and_title_chunks[0]);
+
+            // Render the title
+            let title_paragraph = Paragraph::new(Span::styled(title, style));
+            f.render_widget(title_paragraph, checkbox_and_title_chunks[1]);
+
+            // Now build and render the progress bar in the second chunk
+
+            let progress_bar_width = horizontal_chunks[1].width;
+
+            let progress_bar = build_progress_bar(todo.progress, progress_bar_width as usize);
+
+            let progress_bar_style = if is_blocked(todo, todos) {
+                Style::default().fg(Color::DarkGray)
+            } else {
+                Style::default()
+            };
+            let progress_bar_paragraph =
+                Paragraph::new(Span::styled(progress_bar, progress_bar_style));
+
+            f.render_widget(progress_bar_paragraph, horizontal_chunks[1]);
+        }
+
+        // Render the add button
+        let add_button_text = Span::styled("[     +     ]", Style::default().fg(Color::Green));
+        let add_button_paragraph = Paragraph::new(add_button_text).wrap(Wrap { trim: false });
+
+        f.render_widget(add_button_paragraph, chunks[todos.len()]);
+
+        // Render the details popup on top of the list
+        if let Some(todo) = self
+            .details_id
+            .and_then(|id| todos.iter().find(|todo| todo.id == id))
+        {
+            render_details(
+                f,
+                todo,
+                todos,
+                self.notes_editor.as_ref(),
+                self.details_input.as_ref(),
+            );
+        }
+        if let Some(preview) = &self.import_preview {
+            render_import_preview(f, preview);
+        }
+        if let Some(changes) = &self.reload {
+            if let Some(conflict) = changes.merged.conflicts.first() {
+                render_conflict(f, conflict, changes.merged.conflicts.len());
+            }
+        }
+
+        if let Some(scroll) = self.help_scroll {
+            render_help(f, scroll, self.details_id.is_some());
+        }
+
+        // Render the status bar in the bottom margin of the list
+        let size = f.size();
+        if size.height > 1 {
+            let bar = Rect::new(size.x, size.y + size.height - 2, size.width, 1);
+            render_status_bar(f, bar, self);
+        }
+
+        // Render the command line or the result of the last command on the bottom line
+        if size.height > 0 {
+            let bottom = Rect::new(size.x, size.y + size.height - 1, size.width, 1);
+            if let Some(command) = &self.command_input {
+                let line =
+                    Span::styled(format!(":{}_", command), Style::default().fg(Color::Yellow));
+                f.render_widget(Clear, bottom);
+                f.render_widget(Paragraph::new(line), bottom);
+            } else if let Some(message) = &self.status_message {
+                f.render_widget(Clear, bottom);
+                f.render_widget(Paragraph::new(message.as_str()), bottom);
+            } else if self.read_only {
+                let line = Span::styled(
+                    "Read-only: another ratodui is editing these todos",
+                    Style::default().fg(Color::DarkGray),
+                );
+                f.render_widget(Clear, bottom);
+                f.render_widget(Paragraph::new(line), bottom);
+            }
+        }
+    }
+}
+
+// Function to render the mode, the file and a summary of the list, with a
+// note on changes that haven't been saved yet
+fn render_status_bar<B: Backend>(f: &mut Frame<B>, area: Rect, app: &App) {
+    let todos = &app.todos;
+    let done = todos.iter().filter(|todo| todo.completed).count();
+    let average = match todos.len() {
+        0 => 0,
+        len => {
+            todos
+                .iter()
+                .map(|todo| todo.progress as usize)
+                .sum::<usize>()
+                / len
+        }
+    };
+
+    f.render_widget(Clear, area);
+    let left = Spans::from(vec![
+        Span::styled(
+            format!(" {} ", app.mode().label()),
+            Style::default().add_modifier(Modifier::REVERSED | Modifier::BOLD),
+        ),
+        Span::raw(format!(" {}", app.file_name)),
+    ]);
+    f.render_widget(Paragraph::new(left), area);
+
+    let mut right = vec![Span::styled(
+        format!(
+            "{} open \u{b7} {} done \u{b7} {} total \u{b7} {}% avg ",
+            todos.len() - done,
+            done,
+            todos.len(),
+            average
+        ),
+        Style::default().fg(Color::DarkGray),
+    )];
+    if app.unsaved.is_some() {
+        right.push(Span::styled("unsaved ", Style::default().fg(Color::Yellow)));
+    }
+    f.render_widget(
+        Paragraph::new(Spans::from(right)).alignment(Alignment::Right),
+        area,
+    );
+}
+
+// Helper function to list the keys and mouse gestures for the help popup,
+// from the keymap of the list or of the details popup
+fn help_lines(details: bool) -> Vec<Spans<'static>> {
+    let sections: &[(&str, &[keymap::Binding])] = if details {
+        &[
+            ("Details", keymap::DETAILS),
+            ("Editing the notes", keymap::EDIT_NOTES),
+            ("Editing a field", keymap::EDIT_FIELD),
+        ]
+    } else {
+        &[
+            ("List", keymap::LIST),
+            ("Editing a name", keymap::EDIT_NAME),
+            ("Command line", keymap::COMMAND),
+        ]
+    };
+    let heading = |title: &str| {
+        Spans::from(Span::styled(
+            title.to_string(),
+            Style::default().add_modifier(Modifier::BOLD),
+        ))
+    };
+
+    let mut lines = Vec::new();
+    for (title, bindings) in sections
+        .iter()
+        .chain(&[("Anywhere", keymap::GLOBAL), ("This help", keymap::HELP)])
+    {
+        lines.push(heading(title));
+        for binding in bindings.iter() {
+            let keys = keymap::describe(binding);
+            lines.push(Spans::from(format!("  {:<12}{}", keys, binding.help)));
+        }
+    }
+    lines.push(heading("Mouse"));
+    for (gesture, help) in keymap::MOUSE {
+        lines.push(Spans::from(format!("  {:<30}{}", gesture, help)));
+    }
+    lines
+}
+
+// Function to render the help popup, scrolled down by `scroll` lines
+fn render_help<B: Backend>(f: &mut Frame<B>, scroll: usize, details: bool) {
+    let lines = help_lines(details);
+    let area = centered_rect(72, lines.len() as u16 + 2, f.size());
+    f.render_widget(Clear, area);
+    let block = Block::default()
+        .title(" Keys and mouse ")
+        .borders(Borders::ALL);
+    let paragraph = Paragraph::new(lines)
+        .block(block)
+        .scroll((scroll as u16, 0));
+    f.render_widget(paragraph, area);
+}
+
+// Helper function to place the import preview on the screen
+fn import_preview_area(size: Rect) -> Rect {
+    centered_rect(70, 20, size)
+}
+
+// Function to render the todos an import would add, with their blockers
+// given by their position in the preview
+fn render_import_preview<B: Backend>(f: &mut Frame<B>, preview: &ImportPreview) {
+    let area = import_preview_area(f.size());
+    f.render_widget(Clear, area);
+
+    let block = Block::default()
+        .title(format!(
+            " Import {} todos from {} ({}: merge, {}: cancel) ",
+            preview.todos.len(),
+            preview.source,
+            keymap::hint(keymap::IMPORT_PREVIEW, Command::Confirm),
+            keymap::hint(keymap::IMPORT_PREVIEW, Command::Cancel)
+        ))
+        .borders(Borders::ALL);
+    let inner = block.inner(area);
+    f.render_widget(block, area);
+
+    let lines: Vec<Spans> = preview
+        .todos
+        .iter()
+        .skip(preview.scroll)
+        .take(inner.height as usize)
+        .map(|todo| {
+            let mut line = format!(
+                "#{:<3} {} {:>3}%  ",
+                todo.id,
+                if todo.completed { "[x]" } else { "[ ]" },
+                todo.progress
+            );
+            if let Some(priority) = todo.priority {
+                line.push_str(&format!("({}) ", priority));
+            }
+            line.push_str(&todo.name);
+            if let Some(due) = todo.due {
+                line.push_str(&format!("  due {}", due));
+            }
+            if todo.recurrence.is_some() {
+                line.push_str(" \u{21bb}");
+            }
+            if !todo.tags.is_empty() {
+                line.push_str(&format!("  {}", todo.tags.join(" ")));
+            }
+            if !todo.blocked_by.is_empty() {
+                let ids: Vec<String> = todo
+                    .blocked_by
+                    .iter()
+                    .map(|id| format!("#{}", id))
+                    .collect();
+                line.push_str(&format!("  \u{2298} {}", ids.join(" ")));
+            }
+            let style = if todo.completed {
+                Style::default().fg(Color::DarkGray)
+            } else {
+                Style::default()
+            };
+            Spans::from(Span::styled(line, style))
+        })
+        .collect();
+    f.render_widget(Paragraph::new(lines), inner);
+}
+
+// Function to render the prompt for a todo that was changed both here and
+// by another program, showing the two versions side by side
+fn render_conflict<B: Backend>(f: &mut Frame<B>, conflict: &merge::Conflict, left: usize) {
+    let area = centered_rect(70, 10, f.size());
+    f.render_widget(Clear, area);
+
+    let block = Block::default()
+        .title(format!(
+            " #{} was changed here and by another program ({} left) ",
+            conflict.id, left
+        ))
+        .borders(Borders::ALL);
+    let inner = block.inner(area);
+    f.render_widget(block, area);
+
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(1), Constraint::Length(1)].as_ref())
+        .split(inner);
+    let columns = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
+        .split(rows[0]);
+
+    for (title, todo, column) in [
+        ("Here", conflict.mine.as_ref(), columns[0]),
+        ("Saved elsewhere", conflict.theirs.as_ref(), columns[1]),
+    ] {
+        let mut lines = vec![Spans::from(Span::styled(
+            title,
+            Style::default().add_modifier(Modifier::BOLD),
+        ))];
+        match todo {
+            Some(todo) => {
+                let checkbox = if todo.completed { "[x]" } else { "[ ]" };
+                lines.push(Spans::from(format!("{} {}", checkbox, todo.name)));
+                lines.push(Spans::from(format!("Progress: {}%", todo.progress)));
+                if let Some(due) = todo.due {
+                    lines.push(Spans::from(format!("Due: {}", due)));
+                }
+                if !todo.notes.is_empty() {
+                    lines.push(Spans::from(format!(
+                        "Notes: {} lines",
+                        todo.notes.lines().count()
+                    )));
+                }
+            }
+            None => lines.push(Spans::from("(removed)")),
+        }
+        f.render_widget(Paragraph::new(lines).wrap(Wrap { trim: false }), column);
+    }
+
+    let key = |command| keymap::hint(keymap::CONFLICT, command);
+    let help = Span::styled(
+        format!(
+            "{}: keep this one, {}: take the file's, {}/{}: same for all",
+            key(Command::KeepMine),
+            key(Command::TakeTheirs),
+            key(Command::KeepAllMine),
+            key(Command::TakeAllTheirs)
+        ),
+        Style::default().fg(Color::Yellow),
+    );
+    f.render_widget(Paragraph::new(help), rows[1]);
+}
+
+// Helper function to find the todos still blocking a todo
+fn open_blockers<'a>(todo: &Todo, todos: &'a [Todo]) -> Vec<&'a Todo> {
+    todo.blocked_by
+        .iter()
+        .filter_map(|id| {
+            todos
+                .iter()
+                .find(|other| other.id == *id && !other.completed)
+        })
+        .collect()
+}
+
+// Function to render the details popup for a todo
+fn render_details<B: Backend>(
+    f: &mut Frame<B>,
+    todo: &Todo,
+    todos: &[Todo],
+    notes_editor: Option<&TextEditor>,
+    details_input: Option<&(DetailsField, String)>,
+) {
+    let area = centered_rect(70, 25, f.size());
+    f.render_widget(Clear, area);
+    let key = |command| keymap::hint(keymap::DETAILS, command);
+
+    let block = Block::default()
+        .title(format!(" #{} {} ", todo.id, todo.name))
+        .borders(Borders::ALL);
+    let inner = block.inner(area);
+    f.render_widget(block, area);
+
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(
+            [
+                Constraint::Length(1), // Current progress
+                Constraint::Length(2), // Due date, recurrence, priority and tags
+                Constraint::Length(2), // Dependencies
+                Constraint::Length(4), // Sparkline
+                Constraint::Length(3), // Most recent changes
+                Constraint::Min(3),    // Notes
+            ]
+            .as_ref(),
+        )
+        .split(inner);
+
+    let progress_bar = build_progress_bar(todo.progress, rows[0].width as usize);
+    f.render_widget(Paragraph::new(Span::raw(progress_bar)), rows[0]);
+
+    // Render the schedule, priority and tags, or the input for one of them
+    let schedule = match details_input {
+        Some((field, buffer)) => {
+            let label = match field {
+                DetailsField::Due => "Due (YYYY-MM-DD)",
+                DetailsField::Recurrence => "Repeats (e.g. daily, weekly mon,thu, after 7 days)",
+                DetailsField::BlockedBy => "Blocked by (todo IDs, e.g. 3, 7)",
+                DetailsField::Priority => "Priority (A-Z)",
+                DetailsField::Tags => "Tags (e.g. +project @context)",
+            };
+            vec![Spans::from(Span::styled(
+                format!("{}: {}_", label, buffer),
+                Style::default().fg(Color::Yellow),
+            ))]
+        }
+        None => {
+            let due = todo.due.map_or(String::from("-"), |due| due.to_string());
+            let repeats = todo
+                .recurrence
+                .as_ref()
+                .map_or(String::from("never"), |r| r.to_string());
+            let priority = todo.priority.map_or(String::from("-"), String::from);
+            let tags = if todo.tags.is_empty() {
+                String::from("-")
+            } else {
+                todo.tags.join(" ")
+            };
+            vec![
+                Spans::from(format!(
+                    "Due: {} ({})   Repeats: {} ({})",
+                    due,
+                    key(Command::EditDue),
+                    repeats,
+                    key(Command::EditRecurrence)
+                )),
+                Spans::from(format!(
+                    "Priority: {} ({})   Tags: {} ({})",
+                    priority,
+                    key(Command::EditPriority),
+                    tags,
+                    key(Command::EditTags)
+                )),
+            ]
+        }
+    };
+    f.render_widget(Paragraph::new(schedule), rows[1]);
+
+    // Render what blocks this todo and what completing it would unblock
+    let names = |ids: Vec<&Todo>| -> String {
+        let names: Vec<String> = ids
+            .iter()
+            .map(|todo| format!("#{} {}", todo.id, todo.name))
+            .collect();
+        if names.is_empty() {
+            String::from("-")
+        } else {
+            names.join(", ")
+        }
+    };
+    let dependencies = vec![
+        Spans::from(format!(
+            "Blocked by: {} ({})",
+            names(open_blockers(todo, todos)),
+            key(Command::EditBlockers)
+        )),
+        Spans::from(format!(
+            "Completing unblocks: {}",
+            names(unblocked_by(todos, todo.id))
+        )),
+    ];
+    f.render_widget(Paragraph::new(dependencies), rows[2]);
+
+    // Render the notes, either as the editor or read-only
+    if let Some(editor) = notes_editor {
+        let notes_block = Block::default()
+            .title(format!(
+                " Notes ({} to finish) ",
+                keymap::hint(keymap::EDIT_NOTES, Command::Confirm)
+            ))
+            .borders(Borders::TOP)
+            .border_style(Style::default().fg(Color::Yellow));
+        editor.render(f, notes_block, rows[5]);
+    } else {
+        let notes_block = Block::default()
+            .title(format!(" Notes ({} to edit) ", key(Command::EditNotes)))
+            .borders(Borders::TOP);
+        let notes = Paragraph::new(todo.notes.as_str())
+            .block(notes_block)
+            .wrap(Wrap { trim: false });
+        f.render_widget(notes, rows[5]);
+    }
+
+    if todo.history.is_empty() {
+        let hint = Span::styled(
+            "No progress history yet",
+            Style::default().fg(Color::DarkGray),
+        );
+        f.render_widget(Paragraph::new(hint), rows[3]);
+        return;
+    }
+
+    // One bar per day, showing only as many days as fit
+    let mut daily = daily_progress(&todo.history, Local::now().date_naive());
+    let max_days = rows[3].width as usize;
+    if daily.len() > max_days {
+        daily.drain(..daily.len() - max_days);
+    }
+    let sparkline = Sparkline::default()
+        .data(&daily)
+        .max(100)
+        .style(Style::default().fg(Color::Green));
+    f.render_widget(sparkline, rows[3]);
+
+    let recent: Vec<Spans> = todo
+        .history
+        .iter()
+        .rev()
+        .take(rows[4].height as usize)
+        .map(|entry| {
+            Spans::from(format!(
+                "{}  {}%",
+                entry.at.with_timezone(&Local).format("%Y-%m-%d %H:%M"),
+                entry.progress
+            ))
+        })
+        .collect();
+    f.render_widget(Paragraph::new(recent), rows[4]);
+}
+
+// Helper function to compute a centered rectangle of the given size
+pub fn centered_rect(width: u16, height: u16, area: Rect) -> Rect {
+    let width = width.min(area.width);
+    let height = height.min(area.height);
+    Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    }
+}
+
+// Helper function to turn the progress history into one value per day,
+// carrying the last known progress forward over days without changes
+fn daily_progress(history: &[ProgressEntry], today: NaiveDate) -> Vec<u64> {
+    let mut daily = Vec::new();
+    let Some(first) = history.first() else {
+        return daily;
+    };
+
+    let mut day = first.at.with_timezone(&Local).date_naive();
+    let mut entries = history.iter().peekable();
+    let mut progress = first.progress;
+    while day <= today {
+        while let Some(entry) = entries.peek() {
+            if entry.at.with_timezone(&Local).date_naive() > day {
+                break;
+            }
+            progress = entry.progress;
+            entries.next();
+        }
+        daily.push(progress as u64);
+        day = match day.succ_opt() {
+            Some(next) => next,
+            None => break,
+        };
+    }
+
+    daily
+}
+
+// Function to update the progress of a todo based on mouse x position
+fn update_progress(todo: &mut Todo, area: Rect, mouse_x: u16) {
+    // Position of the '[' character
+    let percent_str = format!(" {}%", todo.progress);
+    let extra_chars = 2 + percent_str.len(); // '[' and ']' and percentage
+
+    if area.width <= extra_chars as u16 {
+        // Not enough space, do nothing
+        return;
+    }
+
+    // Calculate the width of the progress bar
+    let bar_width = area.width - extra_chars as u16;
+
+    // The progress bar starts after the '[' character
+    let progress_bar_start_x = area.x + 1; // Start after '['
+    let progress_bar_end_x = progress_bar_start_x + bar_width;
+
+    if mouse_x >= progress_bar_start_x && mouse_x <= progress_bar_end_x {
+        let relative_x = mouse_x - progress_bar_start_x;
+        let progress = ((relative_x * 100) / bar_width).min(100) as u16;
+        if todo.progress != progress {
+            todo.progress = progress;
+        }
+    }
+}
+
+// Helper function to compute chunks based on the terminal size and todos
+fn compute_chunks(size: Rect, todos: &[Todo], scroll: usize) -> Vec<Rect> {
+    // The bottom line is left to the command line, so the bottom margin
+    // of the list is where the status bar goes
+    let size = Rect {
+        height: size.height.saturating_sub(1),
+        ..size
+    };
+    // Todos scrolled above the top get no room, like those below the bottom
+    let mut constraints = vec![Constraint::Length(0); scroll.min(todos.len())];
+
+    for _ in todos.iter().skip(scroll) {
+        constraints.push(Constraint::Length(1)); // Each todo takes up 1 row
+    }
+
+    // Add constraint for the add button
+    constraints.push(Constraint::Length(1));
+
+    Layout::default()
+        .direction(Direction::Vertical)
+        .margin(1) // Reduce margin to save space
+        .constraints(constraints)
+        .split(size)
+        .to_vec() // Convert Rc<[Rect]> to Vec<Rect>
+}
+
+// Helper function to count the rows compute_chunks has for todos and the add
+// button, between the margins above the status bar
+fn list_rows(size: Rect) -> usize {
+    size.height.saturating_sub(3) as usize
+}
+
+// Helper function to split a todo's line into the checkbox and title, and
+// the progress bar
+fn split_line(area: Rect) -> Vec<Rect> {
+    Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints(
+            [
+                Constraint::Length(35), // Adjusted length for checkbox and title
+                Constraint::Min(1),     // Remaining space for progress bar
+            ]
+            .as_ref(),
+        )
+        .split(area)
+        .to_vec()
+}
+
+// Helper function to check if a point is inside a rectangle
+fn is_inside(pos: (u16, u16), area: Rect) -> bool {
+    pos.0 >= area.x
+        && pos.0 < area.x + area.width
+        && pos.1 >= area.y
+        && pos.1 < area.y + area.height
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossterm::event::KeyModifiers;
+    use ratatui::{backend::TestBackend, buffer::Buffer, Terminal};
+
+    const WIDTH: u16 = 80;
+    const HEIGHT: u16 = 30; // Tall enough for the details popup
+
+    // The progress bars start after the 35 columns of checkbox and title and
+    // the one column margin, with their '[' at x = 36
+    const BAR_START: u16 = 37;
+    const BAR_WIDTH: u16 = WIDTH - 36 - 1 - 2 - 3; // Margin, brackets and " 0%"
+
+    fn app_with(names: &[&str]) -> App {
+        let mut todos = Vec::new();
+        for name in names {
+            push_todo(&mut todos, Todo::new(name));
+        }
+        App::new(todos, Rect::new(0, 0, WIDTH, HEIGHT), false)
+    }
+
+    fn key(code: KeyCode) -> Action {
+        Action::Key(KeyEvent::new(code, KeyModifiers::NONE))
+    }
+
+    fn mouse(kind: MouseEventKind, column: u16, row: u16) -> Action {
+        Action::Mouse(MouseEvent {
+            kind,
+            column,
+            row,
+            modifiers: KeyModifiers::NONE,
+        })
+    }
+
+    fn click(column: u16, row: u16) -> Action {
+        mouse(MouseEventKind::Down(MouseButton::Left), column, row)
+    }
+
+    fn type_text(app: &mut App, text: &str) {
+        for c in text.chars() {
+            app.update(key(KeyCode::Char(c)));
+        }
+    }
+
+    fn render(app: &App) -> Buffer {
+        let mut terminal = Terminal::new(TestBackend::new(WIDTH, HEIGHT)).unwrap();
+        terminal.draw(|f| app.view(f)).unwrap();
+        terminal.backend().buffer().clone()
+    }
+
+    fn line(buffer: &Buffer, y: u16) -> String {
+        (0..buffer.area.width)
+            .map(|x| buffer.get(x, y).symbol.as_str())
+            .collect::<String>()
+            .trim_end()
+            .to_string()
+    }
+
+    fn screen(buffer: &Buffer) -> String {
+        (0..buffer.area.height)
+            .map(|y| line(buffer, y))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    #[test]
+    fn renders_todos_with_checkboxes_and_progress_bars() {
+        let mut app = app_with(&["Write tests", "Ship it"]);
+        app.todos[1].set_completed(true);
+        app.todos[1].progress = 50;
+        let buffer = render(&app);
+
+        assert!(line(&buffer, 1).starts_with(" [ ] Write tests"));
+        assert!(line(&buffer, 1).ends_with(&format!("[{}] 0%", "-".repeat(BAR_WIDTH as usize))));
+        assert!(line(&buffer, 2).starts_with(" [x] Ship it"));
+        assert!(line(&buffer, 2).contains(&format!("[{}", "#".repeat(18))));
+        assert!(line(&buffer, 2).ends_with("] 50%"));
+        assert_eq!(line(&buffer, 3), " [     +     ]");
+    }
+
+    #[test]
+    fn keys_move_the_selection() {
+        let mut app = app_with(&["One", "Two", "Three"]);
+        let is_bold = |app: &App, y: u16| {
+            let buffer = render(app);
+            buffer.get(5, y).modifier.contains(Modifier::BOLD)
+        };
+        assert!(is_bold(&app, 1));
+
+        app.update(key(KeyCode::Char('j')));
+        app.update(key(KeyCode::Down));
+        assert!(is_bold(&app, 3));
+        assert!(!is_bold(&app, 1));
+
+        // The selection stops at the last todo
+        app.update(key(KeyCode::Down));
+        assert!(is_bold(&app, 3));
+
+        app.update(key(KeyCode::Char('k')));
+        assert!(is_bold(&app, 2));
+    }
+
+    #[test]
+    fn clicking_a_title_edits_the_name() {
+        let mut app = app_with(&["New Todo"]);
+        app.update(click(10, 1));
+        // The placeholder name isn't kept
+        assert!(line(&render(&app), 1).starts_with(" [ ] _"));
+
+        type_text(&mut app, "Groceries");
+        assert!(line(&render(&app), 1).starts_with(" [ ] Groceries_"));
+        assert!(!app.needs_save());
+
+        app.update(key(KeyCode::Enter));
+        assert_eq!(app.todos[0].name, "Groceries");
+        assert!(line(&render(&app), 1).starts_with(" [ ] Groceries "));
+        assert!(app.needs_save());
+    }
+
+    #[test]
+    fn clicking_elsewhere_finishes_editing() {
+        let mut app = app_with(&["Old name", "Other"]);
+        app.update(click(10, 1));
+        // The release of the click that started editing is ignored
+        app.update(mouse(MouseEventKind::Up(MouseButton::Left), 10, 1));
+        app.update(key(KeyCode::Backspace));
+        app.update(key(KeyCode::Backspace));
+        app.update(key(KeyCode::Backspace));
+        app.update(click(2, 2));
+
+        assert_eq!(app.todos[0].name, "Old n");
+        assert!(app.todos[1].completed);
+    }
+
+    #[test]
+    fn q_is_typed_while_editing() {
+        let mut app = app_with(&["Name"]);
+        app.update(click(10, 1));
+        assert_eq!(app.update(key(KeyCode::Char('q'))), None);
+        app.update(key(KeyCode::Tab));
+        assert_eq!(app.todos[0].name, "Nameq");
+        assert!(app.needs_save());
+    }
+
+    #[test]
+    fn ctrl_z_suspends_and_the_edit_is_kept_on_exit() {
+        let mut app = app_with(&["Name"]);
+        app.update(click(10, 1));
+        let ctrl_z = Action::Key(KeyEvent::new(KeyCode::Char('z'), KeyModifiers::CONTROL));
+        assert_eq!(app.update(ctrl_z), Some(Effect::Suspend));
+        type_text(&mut app, "d");
+        app.finish_pending_edits();
+        assert_eq!(app.todos[0].name, "Named");
+        assert!(app.needs_save());
+    }
+
+    #[test]
+    fn clicking_the_checkbox_toggles_completion() {
+        let mut app = app_with(&["Task"]);
+        app.update(click(2, 1));
+        assert!(app.todos[0].completed);
+        assert!(app.todos[0].completed_at.is_some());
+        assert!(line(&render(&app), 1).starts_with(" [x] Task"));
+        assert!(app.needs_save());
+
+        app.update(click(2, 1));
+        assert!(!app.todos[0].completed);
+        assert!(app.todos[0].completed_at.is_none());
+    }
+
+    #[test]
+    fn blocked_todos_cant_be_completed() {
+        let mut app = app_with(&["First", "Second"]);
+        app.todos[1].blocked_by = vec![app.todos[0].id];
+        assert!(line(&render(&app), 2).starts_with(" [ ] Second \u{2298}"));
+
+        app.update(click(2, 2));
+        assert!(!app.todos[1].completed);
+        let message = format!("Blocked by #{} First", app.todos[0].id);
+        assert_eq!(app.status_message.as_deref(), Some(message.as_str()));
+
+        app.update(click(2, 1));
+        app.update(click(2, 2));
+        assert!(app.todos[1].completed);
+    }
+
+    #[test]
+    fn completing_a_recurring_todo_spawns_the_next_one() {
+        let mut app = app_with(&["Water plants"]);
+        app.todos[0].recurrence = Recurrence::parse("daily");
+        app.update(click(2, 1));
+
+        assert_eq!(app.todos.len(), 2);
+        assert!(app.todos[0].recurrence.is_none());
+        assert_eq!(app.todos[1].name, "Water plants");
+        assert!(app.todos[1].recurrence.is_some());
+        assert!(line(&render(&app), 2).starts_with(" [ ] Water plants \u{21bb}"));
+    }
+
+    #[test]
+    fn recurrences_without_a_next_date_end() {
+        let mut app = app_with(&["Someday"]);
+        app.todos[0].recurrence = Recurrence::parse("every 4000000000 days");
+        app.update(click(2, 1));
+
+        assert_eq!(app.todos.len(), 1);
+        assert!(app.todos[0].completed);
+        assert!(app.todos[0].recurrence.is_none());
+    }
+
+    #[test]
+    fn dragging_the_progress_bar_saves_once_when_released() {
+        let mut app = app_with(&["Task"]);
+        app.update(click(BAR_START + BAR_WIDTH / 2, 1));
+        assert_eq!(app.todos[0].progress, 50);
+        // Drag steps wait for the drag to settle
+        assert!(!app.needs_save());
+        assert!(line(&render(&app), HEIGHT - 2).ends_with(" unsaved"));
+
+        app.update(mouse(MouseEventKind::Drag(MouseButton::Left), BAR_START, 1));
+        assert_eq!(app.todos[0].progress, 0);
+        app.update(mouse(
+            MouseEventKind::Drag(MouseButton::Left),
+            BAR_START + BAR_WIDTH,
+            5, // The pointer may leave the line while dragging
+        ));
+        assert_eq!(app.todos[0].progress, 100);
+        assert!(!app.needs_save());
+
+        app.update(mouse(
+            MouseEventKind::Up(MouseButton::Left),
+            BAR_START + BAR_WIDTH,
+            5,
+        ));
+        assert_eq!(app.todos[0].history.len(), 1);
+        assert_eq!(app.todos[0].history[0].progress, 100);
+        assert!(app.needs_save());
+        assert!(line(&render(&app), 1).ends_with("] 100%"));
+    }
+
+    #[test]
+    fn the_add_button_adds_a_todo() {
+        let mut app = app_with(&["One"]);
+        app.update(click(5, 2));
+
+        assert_eq!(app.todos.len(), 2);
+        assert_eq!(app.todos[1].id, 2);
+        assert!(app.needs_save());
+        let buffer = render(&app);
+        assert!(line(&buffer, 2).starts_with(" [ ] New Todo"));
+        assert_eq!(line(&buffer, 3), " [     +     ]");
+    }
+
+    #[test]
+    fn enter_opens_the_details_popup() {
+        let mut app = app_with(&["One", "Two"]);
+        app.update(key(KeyCode::Down));
+        app.update(key(KeyCode::Enter));
+        let shown = screen(&render(&app));
+        assert!(shown.contains("#2 Two"));
+        assert!(shown.contains("Due: - (d)   Repeats: never (r)"));
+        assert!(shown.contains("No progress history yet"));
+
+        // Set the due date from the popup
+        app.update(key(KeyCode::Char('d')));
+        assert!(screen(&render(&app)).contains("Due (YYYY-MM-DD): _"));
+        type_text(&mut app, "2030-01-31");
+        app.update(key(KeyCode::Enter));
+        assert_eq!(app.todos[1].due, NaiveDate::from_ymd_opt(2030, 1, 31));
+        assert!(screen(&render(&app)).contains("Due: 2030-01-31 (d)"));
+
+        app.update(key(KeyCode::Esc));
+        assert!(!screen(&render(&app)).contains("#2 Two"));
+    }
+
+    #[test]
+    fn right_click_opens_the_details_popup() {
+        let mut app = app_with(&["One", "Two"]);
+        app.update(mouse(MouseEventKind::Down(MouseButton::Right), 10, 2));
+        assert!(screen(&render(&app)).contains("#2 Two"));
+
+        // Any click closes it without acting on the list
+        app.update(click(2, 1));
+        assert!(!screen(&render(&app)).contains("#2 Two"));
+        assert!(!app.todos[0].completed);
+    }
+
+    #[test]
+    fn notes_are_edited_in_the_popup() {
+        let mut app = app_with(&["One"]);
+        app.update(key(KeyCode::Enter));
+        app.update(key(KeyCode::Char('e')));
+        type_text(&mut app, "first");
+        app.update(key(KeyCode::Enter));
+        type_text(&mut app, "second");
+        app.update(key(KeyCode::Esc));
+
+        assert_eq!(app.todos[0].notes, "first\nsecond");
+        assert!(app.needs_save());
+        app.update(key(KeyCode::Esc));
+        assert!(line(&render(&app), 1).starts_with(" [ ] One \u{2261}"));
+    }
+
+    #[test]
+    fn q_quits_and_e_asks_for_the_editor() {
+        let mut app = app_with(&["One", "Two"]);
+        app.update(key(KeyCode::Down));
+        assert_eq!(
+            app.update(key(KeyCode::Char('E'))),
+            Some(Effect::EditExternally(2))
+        );
+        assert_eq!(app.update(key(KeyCode::Char('q'))), Some(Effect::Quit));
+    }
+
+    #[test]
+    fn commands_are_typed_on_the_bottom_line() {
+        let mut app = app_with(&["One"]);
+        app.update(key(KeyCode::Char(':')));
+        type_text(&mut app, "export csvv");
+        app.update(key(KeyCode::Backspace));
+        assert_eq!(line(&render(&app), HEIGHT - 1), ":export csv_");
+
+        assert_eq!(
+            app.update(key(KeyCode::Enter)),
+            Some(Effect::RunCommand(String::from("export csv")))
+        );
+        // Typing ':' and 'q' into a command doesn't quit
+        app.update(key(KeyCode::Char(':')));
+        assert_eq!(app.update(key(KeyCode::Char('q'))), None);
+        app.update(key(KeyCode::Esc));
+        assert_eq!(line(&render(&app), HEIGHT - 1), "");
+    }
+
+    #[test]
+    fn read_only_views_refuse_changes() {
+        let mut app = app_with(&["One", "Two"]);
+        app.read_only = true;
+        assert_eq!(
+            line(&render(&app), HEIGHT - 1),
+            "Read-only: another ratodui is editing these todos"
+        );
+
+        app.update(click(2, 1));
+        app.update(click(5, 3));
+        assert!(!app.todos[0].completed);
+        assert_eq!(app.todos.len(), 2);
+        assert!(!app.needs_save());
+
+        // Looking around is fine
+        app.update(key(KeyCode::Down));
+        app.update(key(KeyCode::Enter));
+        assert!(screen(&render(&app)).contains("#2 Two"));
+        app.update(key(KeyCode::Char('e')));
+        assert!(app.notes_editor.is_none());
+        app.update(key(KeyCode::Esc));
+        assert!(app.details_id.is_none());
+    }
+
+    #[test]
+    fn conflicts_are_resolved_one_by_one() {
+        let mut app = app_with(&["One", "Two"]);
+        let base = app.todos.clone();
+        app.todos[0].name = String::from("One here");
+        app.todos[1].progress = 10;
+        let mut theirs = base.clone();
+        theirs[0].name = String::from("One there");
+        theirs[1].progress = 90;
+        let merged = merge::merge(&base, &app.todos, &theirs);
+        app.start_reload(Reload { merged, theirs });
+
+        let shown = screen(&render(&app));
+        assert!(shown.contains("#1 was changed here and by another program (2 left)"));
+        assert!(shown.contains("[ ] One here"));
+        assert!(shown.contains("[ ] One there"));
+        // Changes wait for the conflicts to be resolved
+        assert!(!app.needs_save());
+        app.update(click(2, 1));
+        assert!(!app.todos[0].completed);
+
+        app.update(key(KeyCode::Char('t')));
+        assert!(
+            screen(&render(&app)).contains("#2 was changed here and by another program (1 left)")
+        );
+        app.update(key(KeyCode::Char('m')));
+
+        assert!(app.reload.is_none());
+        assert_eq!(app.todos[0].name, "One there");
+        assert_eq!(app.todos[1].progress, 10);
+        assert_eq!(
+            app.status_message.as_deref(),
+            Some("Merged the changes of the other program")
+        );
+        assert!(app.needs_save());
+    }
+
+    #[test]
+    fn the_status_bar_shows_the_mode_file_and_counts() {
+        let mut app = app_with(&["One", "Two", "Three"]);
+        app.file_name = String::from("todos.json");
+        app.todos[0].set_completed(true);
+        app.todos[0].progress = 100;
+        app.todos[1].progress = 50;
+        let status = line(&render(&app), HEIGHT - 2);
+        assert!(status.starts_with(" NORMAL  todos.json"));
+        assert!(status.ends_with("2 open \u{b7} 1 done \u{b7} 3 total \u{b7} 50% avg"));
+
+        app.update(click(10, 2));
+        assert!(line(&render(&app), HEIGHT - 2).starts_with(" EDIT "));
+        app.update(key(KeyCode::Enter));
+        app.update(key(KeyCode::Char(':')));
+        assert!(line(&render(&app), HEIGHT - 2).starts_with(" COMMAND "));
+    }
+
+    #[test]
+    fn question_mark_shows_the_keys_of_the_keymap() {
+        let mut app = app_with(&["One"]);
+        app.update(key(KeyCode::Char('?')));
+        let help = screen(&render(&app));
+        for binding in keymap::LIST.iter().chain(keymap::GLOBAL) {
+            assert!(help.contains(binding.help), "{} is missing", binding.help);
+        }
+        for (gesture, _) in keymap::MOUSE {
+            assert!(help.contains(gesture), "{} is missing", gesture);
+        }
+        // Keys go to the help while it is open
+        assert_eq!(app.update(key(KeyCode::Char('q'))), None);
+        assert!(!screen(&render(&app)).contains("Keys and mouse"));
+
+        // The details popup has keys of its own
+        app.update(key(KeyCode::Enter));
+        app.update(key(KeyCode::Char('?')));
+        let help = screen(&render(&app));
+        assert!(help.contains("Set the due date"));
+        app.update(key(KeyCode::Esc));
+        assert!(screen(&render(&app)).contains("Due: -"));
+    }
+
+    #[test]
+    fn resizing_keeps_the_selection_on_screen() {
+        let names: Vec<String> = (1..=10).map(|i| format!("Todo {}", i)).collect();
+        let mut app = app_with(&names.iter().map(String::as_str).collect::<Vec<_>>());
+        for _ in 0..9 {
+            app.update(key(KeyCode::Char('j')));
+        }
+        assert_eq!(app.selected, 9);
+
+        // Only five rows fit between the top margin and the status bar, so
+        // the list scrolls to the last todo and the add button
+        app.update(Action::Resize(WIDTH, 8));
+        assert_eq!(app.area, Rect::new(0, 0, WIDTH, 8));
+        assert_eq!(app.selected, 9);
+        assert!(line(&render(&app), 1).starts_with(" [ ] Todo 7"));
+        assert!(line(&render(&app), 4).starts_with(" [ ] Todo 10"));
+        assert!(line(&render(&app), 5).starts_with(" [     +     ]"));
+
+        // Growing again scrolls back as far as there is room
+        app.update(Action::Resize(WIDTH, 12));
+        assert!(line(&render(&app), 1).starts_with(" [ ] Todo 3"));
+    }
+
+    #[test]
+    fn the_list_scrolls_with_the_selection() {
+        let names: Vec<String> = (1..=40).map(|i| format!("Todo {}", i)).collect();
+        let mut app = app_with(&names.iter().map(String::as_str).collect::<Vec<_>>());
+        let rows = (HEIGHT - 3) as usize;
+        for _ in 0..rows {
+            app.update(key(KeyCode::Char('j')));
+        }
+        let shown = screen(&render(&app));
+        assert!(shown.contains(&format!("Todo {}", rows + 1)));
+        assert!(!shown.contains("Todo 1 "));
+        assert!(line(&render(&app), 1).starts_with(" [ ] Todo 2"));
+
+        // Clicks go to the todos where they are shown
+        app.update(click(2, 1));
+        assert!(app.todos[1].completed);
+
+        for _ in 0..rows {
+            app.update(key(KeyCode::Char('k')));
+        }
+        assert!(line(&render(&app), 1).starts_with(" [ ] Todo 1"));
+    }
+
+    #[test]
+    fn resizing_clamps_the_import_preview_scroll() {
+        let mut app = app_with(&["One"]);
+        app.import_preview = Some(ImportPreview {
+            source: String::from("list.txt"),
+            todos: (0..30)
+                .map(|i| Todo::new(&format!("Imported {}", i)))
+                .collect(),
+            scroll: 25,
+        });
+        // The popup shows 18 todos at this height, so the last 18 fill it
+        app.update(Action::Resize(WIDTH, HEIGHT));
+        assert_eq!(app.import_preview.as_ref().unwrap().scroll, 12);
+        assert!(screen(&render(&app)).contains("Imported 29"));
+    }
+
+    #[test]
+    fn import_previews_are_confirmed_before_merging() {
+        let mut app = app_with(&["One"]);
+        app.import_preview = Some(ImportPreview {
+            source: String::from("list.txt"),
+            todos: vec![Todo::new("Imported")],
+            scroll: 0,
+        });
+        assert!(screen(&render(&app)).contains("Import 1 todos from list.txt"));
+
+        app.update(key(KeyCode::Enter));
+        assert_eq!(app.todos.len(), 2);
+        assert_eq!(app.todos[1].name, "Imported");
+        assert_eq!(
+            app.status_message.as_deref(),
+            Some("Imported 1 todos from list.txt")
+        );
+        assert!(app.needs_save());
+    }
+}
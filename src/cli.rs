@@ -0,0 +1,705 @@
+// src/cli.rs
+
+use chrono::Local;
+use ratodui::{
+    bind_markdown, config, crypto, formats, git, git::Synced, is_blocked, lock, merge::Conflict,
+    next_occurrence, push_todo, read_archive, read_todos, record_progress, save_todos, storage,
+    storage::Backend, Todo,
+};
+use std::{
+    env, fs,
+    io::{self, BufRead, BufReader, Write},
+    path::Path,
+};
+
+// Exit codes of the non-interactive commands
+pub const EXIT_OK: i32 = 0;
+pub const EXIT_FAILURE: i32 = 1;
+pub const EXIT_USAGE: i32 = 2;
+pub const EXIT_NOT_FOUND: i32 = 3;
+pub const EXIT_AMBIGUOUS: i32 = 4;
+
+const USAGE: &str = "\
+Usage: ratodui [--passphrase-fd N] [COMMAND]
+
+Without a command the interactive TUI is started.
+
+Commands:
+  add <name> [--progress N]    Add a new todo
+  list [--all] [--json]        List open todos (--all includes completed ones)
+  done <id|pattern>            Mark a todo as completed
+  progress <id|pattern> <N>    Set the progress of a todo to N percent
+  rm <id|pattern>              Remove a todo
+  import <format> <file>       Add the todos from a file
+  export <format> [file]       Write all todos to a file, or to stdout
+  bind [--overwrite] <file.md> Keep the checklist in a markdown file in sync
+                               (--overwrite replaces items that aren't todos)
+  unbind                       Stop syncing the markdown file
+  archive [pattern]            List archived todos, optionally only matching ones
+  migrate <json|sqlite>        Move the todos to another storage backend
+  sync [remote] [--ours|--theirs]
+                               Pull the todos from a git remote, merge them
+                               todo by todo and push the result
+  encrypt                      Encrypt todos.json with a passphrase
+  decrypt                      Store todos.json in plain text again
+  help                         Show this message
+
+A pattern matches todos whose name contains it, ignoring case.
+Formats: todotxt, markdown, ical, csv (csv exports include archived todos);
+taskwarrior (`task export` JSON) and org can only be imported
+
+Storage: todos are kept in todos.json unless `migrate sqlite` moved them to
+an SQLite database; the choice is stored in config.json. `sync <remote>`
+turns the data directory into a git repository where every save is a commit,
+and remembers the remote for later syncs. Todos changed on both sides are
+asked about, or settled with --ours or --theirs.
+
+Encryption: the passphrase of encrypted todos, or the new one for `encrypt`,
+is read from RATODUI_PASSPHRASE, or from the first line of file descriptor N
+with --passphrase-fd N. Bound markdown files and exports stay in plain text.
+
+Exit codes: 0 success, 1 failure, 2 usage error, 3 no matching todo,
+4 pattern matches more than one todo";
+
+// Run a command given on the command line and return the process exit code
+pub fn run(args: &[String]) -> i32 {
+    let (passphrase_fd, args) = match args {
+        [flag, fd, rest @ ..] if flag == "--passphrase-fd" => (Some(fd.as_str()), rest),
+        _ => (None, args),
+    };
+    let (command, rest) = match args.split_first() {
+        Some((command, rest)) => (command.as_str(), rest),
+        None => return usage_error("missing command"),
+    };
+    let passphrase = match read_passphrase(passphrase_fd) {
+        Ok(passphrase) => passphrase,
+        Err(e) => {
+            eprintln!("Failed to read the passphrase: {}", e);
+            return EXIT_FAILURE;
+        }
+    };
+
+    // Hold the update lock for the whole command, so no other ratodui can
+    // save between reading the todos and writing them back. Migrations take
+    // the lock of the store they move away from themselves.
+    let _lock = (command != "migrate")
+        .then(storage::lock_for_update)
+        .flatten();
+
+    // Encrypted todos are unlocked before any command reads them
+    if let (Some(contents), Some(passphrase)) = (storage::locked_contents(), &passphrase) {
+        if let Err(e) = crypto::unlock(&contents, passphrase) {
+            eprintln!("ratodui: {}", e);
+            return EXIT_FAILURE;
+        }
+    }
+
+    match command {
+        "add" => add(rest),
+        "list" | "ls" => list(rest),
+        "done" => done(rest),
+        "progress" => progress(rest),
+        "rm" => remove(rest),
+        "import" => import(rest),
+        "export" => export(rest),
+        "bind" => bind(rest),
+        "unbind" => unbind(rest),
+        "archive" => archive(rest),
+        "migrate" => migrate(rest),
+        "sync" => sync(rest),
+        "encrypt" => encrypt(rest, passphrase.as_deref()),
+        "decrypt" => decrypt(rest),
+        "help" | "--help" | "-h" => {
+            println!("{}", USAGE);
+            EXIT_OK
+        }
+        _ => usage_error(&format!("unknown command '{}'", command)),
+    }
+}
+
+fn add(args: &[String]) -> i32 {
+    let mut name = None;
+    let mut progress = 0;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--progress" {
+            match args.next().and_then(|value| parse_progress(value)) {
+                Some(value) => progress = value,
+                None => return usage_error("--progress expects a number from 0 to 100"),
+            }
+        } else if name.is_none() {
+            name = Some(arg.as_str());
+        } else {
+            return usage_error(&format!("unexpected argument '{}'", arg));
+        }
+    }
+    let Some(name) = name else {
+        return usage_error("add expects a name");
+    };
+
+    let mut todos = match read() {
+        Ok(todos) => todos,
+        Err(code) => return code,
+    };
+    let mut todo = Todo::new(name);
+    todo.progress = progress;
+    if progress > 0 {
+        record_progress(&mut todo);
+    }
+    push_todo(&mut todos, todo);
+    if let Err(code) = save(&todos) {
+        return code;
+    }
+
+    println!("{}", todos[todos.len() - 1].id);
+    EXIT_OK
+}
+
+fn list(args: &[String]) -> i32 {
+    let mut all = false;
+    let mut json = false;
+    for arg in args {
+        match arg.as_str() {
+            "--all" | "-a" => all = true,
+            "--json" => json = true,
+            _ => return usage_error(&format!("unexpected argument '{}'", arg)),
+        }
+    }
+
+    let todos = match read() {
+        Ok(todos) => todos,
+        Err(code) => return code,
+    };
+    let todos: Vec<&Todo> = todos.iter().filter(|todo| all || !todo.completed).collect();
+
+    if json {
+        match serde_json::to_string_pretty(&todos) {
+            Ok(json) => println!("{}", json),
+            Err(e) => {
+                eprintln!("Failed to serialize todos: {}", e);
+                return EXIT_FAILURE;
+            }
+        }
+    } else {
+        for todo in todos {
+            let checkbox = if todo.completed { "[x]" } else { "[ ]" };
+            let due = todo
+                .due
+                .map(|due| format!("  due {}", due))
+                .unwrap_or_default();
+            println!(
+                "{:>4} {} {:>3}%  {}{}",
+                todo.id, checkbox, todo.progress, todo.name, due
+            );
+        }
+    }
+    EXIT_OK
+}
+
+fn done(args: &[String]) -> i32 {
+    let [query] = args else {
+        return usage_error("done expects an id or pattern");
+    };
+
+    let mut todos = match read() {
+        Ok(todos) => todos,
+        Err(code) => return code,
+    };
+    let i = match find_todo(&todos, query) {
+        Ok(i) => i,
+        Err(code) => return code,
+    };
+    if is_blocked(&todos[i], &todos) {
+        eprintln!(
+            "#{} {} is blocked by other todos",
+            todos[i].id, todos[i].name
+        );
+        return EXIT_FAILURE;
+    }
+
+    todos[i].set_completed(true);
+    if let Some(next) = next_occurrence(&mut todos[i], Local::now().date_naive()) {
+        push_todo(&mut todos, next);
+    }
+    if let Err(code) = save(&todos) {
+        return code;
+    }
+    EXIT_OK
+}
+
+fn progress(args: &[String]) -> i32 {
+    let [query, value] = args else {
+        return usage_error("progress expects an id or pattern and a number");
+    };
+    let Some(value) = parse_progress(value) else {
+        return usage_error("progress expects a number from 0 to 100");
+    };
+
+    let mut todos = match read() {
+        Ok(todos) => todos,
+        Err(code) => return code,
+    };
+    let i = match find_todo(&todos, query) {
+        Ok(i) => i,
+        Err(code) => return code,
+    };
+
+    todos[i].progress = value;
+    record_progress(&mut todos[i]);
+    if let Err(code) = save(&todos) {
+        return code;
+    }
+    EXIT_OK
+}
+
+fn remove(args: &[String]) -> i32 {
+    let [query] = args else {
+        return usage_error("rm expects an id or pattern");
+    };
+
+    let mut todos = match read() {
+        Ok(todos) => todos,
+        Err(code) => return code,
+    };
+    let i = match find_todo(&todos, query) {
+        Ok(i) => i,
+        Err(code) => return code,
+    };
+
+    let removed = todos.remove(i);
+    // Nothing can be blocked by a todo that no longer exists
+    for todo in &mut todos {
+        todo.blocked_by.retain(|id| *id != removed.id);
+    }
+    if let Err(code) = save(&todos) {
+        return code;
+    }
+    EXIT_OK
+}
+
+fn import(args: &[String]) -> i32 {
+    let [format, path] = args else {
+        return usage_error("import expects a format and a file");
+    };
+
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) => {
+            eprintln!("Failed to read {}: {}", path, e);
+            return EXIT_FAILURE;
+        }
+    };
+    let imported = match formats::import(format, &contents) {
+        Ok(imported) => imported,
+        Err(e) => return usage_error(&e),
+    };
+
+    let mut todos = match read() {
+        Ok(todos) => todos,
+        Err(code) => return code,
+    };
+    let count = formats::merge(&mut todos, imported);
+    if let Err(code) = save(&todos) {
+        return code;
+    }
+
+    println!("Imported {} todos", count);
+    EXIT_OK
+}
+
+fn export(args: &[String]) -> i32 {
+    let (format, path) = match args {
+        [format] => (format, None),
+        [format, path] => (format, Some(path)),
+        _ => return usage_error("export expects a format and optionally a file"),
+    };
+
+    let todos = match read() {
+        Ok(todos) => todos,
+        Err(code) => return code,
+    };
+    let archived = match read_archive(None) {
+        Ok(archived) => archived,
+        Err(e) => {
+            eprintln!("ratodui: {}", e);
+            return EXIT_FAILURE;
+        }
+    };
+    let contents = match formats::export(format, &todos, &archived) {
+        Ok(contents) => contents,
+        Err(e) => return usage_error(&e),
+    };
+
+    match path {
+        Some(path) => {
+            if let Err(e) = fs::write(path, contents) {
+                eprintln!("Failed to write {}: {}", path, e);
+                return EXIT_FAILURE;
+            }
+        }
+        None => print!("{}", contents),
+    }
+    EXIT_OK
+}
+
+fn bind(args: &[String]) -> i32 {
+    let mut path = None;
+    let mut overwrite = false;
+    for arg in args {
+        match arg.as_str() {
+            "--overwrite" => overwrite = true,
+            _ if path.is_none() => path = Some(arg),
+            _ => return usage_error(&format!("unexpected argument '{}'", arg)),
+        }
+    }
+    let Some(path) = path else {
+        return usage_error("bind expects a markdown file");
+    };
+    // The binding is only stored for todos we are up to date with
+    let todos = match read() {
+        Ok(todos) => todos,
+        Err(code) => return code,
+    };
+    if let Err(e) = bind_markdown(Some(Path::new(path)), &todos, overwrite) {
+        eprintln!("Failed to bind {}: {}", path, e);
+        return EXIT_FAILURE;
+    }
+
+    // Write the checklist right away
+    match save(&todos) {
+        Ok(()) => EXIT_OK,
+        Err(code) => code,
+    }
+}
+
+fn unbind(args: &[String]) -> i32 {
+    if !args.is_empty() {
+        return usage_error("unbind expects no arguments");
+    }
+    let todos = match read() {
+        Ok(todos) => todos,
+        Err(code) => return code,
+    };
+    if let Err(e) = bind_markdown(None, &todos, false) {
+        eprintln!("Failed to unbind: {}", e);
+        return EXIT_FAILURE;
+    }
+    EXIT_OK
+}
+
+fn archive(args: &[String]) -> i32 {
+    let pattern = match args {
+        [] => None,
+        [pattern] => Some(pattern.as_str()),
+        _ => return usage_error("archive expects at most one pattern"),
+    };
+
+    let archived = match read_archive(pattern) {
+        Ok(archived) => archived,
+        Err(e) => {
+            eprintln!("ratodui: {}", e);
+            return EXIT_FAILURE;
+        }
+    };
+    for todo in archived {
+        let completed_on = todo
+            .completed_at
+            .map(|at| at.with_timezone(&Local).date_naive().to_string())
+            .unwrap_or_default();
+        println!("{:>4} {:>10}  {}", todo.id, completed_on, todo.name);
+    }
+    EXIT_OK
+}
+
+fn migrate(args: &[String]) -> i32 {
+    let [backend] = args else {
+        return usage_error("migrate expects a storage backend");
+    };
+    let Some(to) = Backend::parse(backend) else {
+        return usage_error(&format!("unknown storage backend '{}'", backend));
+    };
+    let mut config = config::load();
+    if config.storage == to {
+        eprintln!("The todos are already stored with {}", to.name());
+        return EXIT_FAILURE;
+    }
+
+    let (count, archived) = match storage::migrate(config.storage, to) {
+        Ok(counts) => counts,
+        Err(e) => {
+            eprintln!("Failed to migrate to {}: {}", to.name(), e);
+            return EXIT_FAILURE;
+        }
+    };
+    config.storage = to;
+    if let Err(e) = config::save(&config) {
+        eprintln!("Failed to save config.json: {}", e);
+        return EXIT_FAILURE;
+    }
+
+    println!(
+        "Moved {} todos and {} archived todos to {}",
+        count,
+        archived,
+        to.name()
+    );
+    EXIT_OK
+}
+
+fn sync(args: &[String]) -> i32 {
+    let mut remote = None;
+    let mut side = None;
+    for arg in args {
+        match arg.as_str() {
+            "--ours" => side = Some(false),
+            "--theirs" => side = Some(true),
+            _ if remote.is_none() => remote = Some(arg.clone()),
+            _ => return usage_error(&format!("unexpected argument '{}'", arg)),
+        }
+    }
+
+    let mut config = config::load();
+    if let Some(remote) = remote {
+        config.git = true;
+        config.remote = Some(remote);
+        if let Err(e) = config::save(&config) {
+            eprintln!("Failed to save config.json: {}", e);
+            return EXIT_FAILURE;
+        }
+    }
+    let Some(remote) = config.remote else {
+        return usage_error("sync expects a remote the first time");
+    };
+    if config.storage != Backend::Json {
+        eprintln!("Syncing needs the todos in todos.json; run `ratodui migrate json` first");
+        return EXIT_FAILURE;
+    }
+    let storage = match storage::configured() {
+        Ok(storage) => storage,
+        Err(e) => {
+            eprintln!("ratodui: {}", e);
+            return EXIT_FAILURE;
+        }
+    };
+    // The TUI would keep saving its own version on top of the pulled one
+    if let Ok(None) = lock::try_lock_instance(storage.path()) {
+        eprintln!("Quit the running ratodui before syncing");
+        return EXIT_FAILURE;
+    }
+
+    let synced = git::sync(storage.path(), &remote, |conflict| {
+        side.or_else(|| ask_version(conflict))
+    });
+    match synced {
+        Ok(Synced::UpToDate) => println!("Already up to date with {}", remote),
+        Ok(Synced::Pushed) => println!("Pushed the todos to {}", remote),
+        Ok(Synced::Pulled) => println!("Pulled the todos from {}", remote),
+        Ok(Synced::Merged { conflicts }) => println!(
+            "Merged the todos from {} ({} changed on both sides) and pushed the result",
+            remote, conflicts
+        ),
+        Err(e) => {
+            eprintln!("Failed to sync: {}", e);
+            return EXIT_FAILURE;
+        }
+    }
+    EXIT_OK
+}
+
+fn encrypt(args: &[String], passphrase: Option<&str>) -> i32 {
+    if !args.is_empty() {
+        return usage_error("encrypt expects no arguments");
+    }
+    let Some(passphrase) = passphrase.filter(|passphrase| !passphrase.is_empty()) else {
+        return usage_error(&format!(
+            "encrypt expects the new passphrase in {} or with --passphrase-fd",
+            crypto::PASSPHRASE_VAR
+        ));
+    };
+    if let Err(code) = check_encryption_change() {
+        return code;
+    }
+    if crypto::is_unlocked() {
+        eprintln!("todos.json is already encrypted");
+        return EXIT_FAILURE;
+    }
+
+    if let Err(e) = storage::set_encryption(Some(passphrase)) {
+        eprintln!("Failed to encrypt todos.json: {}", e);
+        return EXIT_FAILURE;
+    }
+    println!("Encrypted todos.json");
+    if config::current().git {
+        println!("Earlier commits in the data directory still hold the todos in plain text");
+    }
+    for backup in storage::backups() {
+        println!(
+            "{} still holds the todos in plain text; remove it to keep them private",
+            backup.display()
+        );
+    }
+    EXIT_OK
+}
+
+fn decrypt(args: &[String]) -> i32 {
+    if !args.is_empty() {
+        return usage_error("decrypt expects no arguments");
+    }
+    if let Err(code) = check_encryption_change() {
+        return code;
+    }
+    if !crypto::is_unlocked() {
+        eprintln!("todos.json isn't encrypted");
+        return EXIT_FAILURE;
+    }
+
+    if let Err(e) = storage::set_encryption(None) {
+        eprintln!("Failed to decrypt todos.json: {}", e);
+        return EXIT_FAILURE;
+    }
+    println!("Decrypted todos.json");
+    EXIT_OK
+}
+
+// Check that encryption can be turned on or off: the todos have to be in
+// todos.json, unlocked, and no TUI may be using them
+fn check_encryption_change() -> Result<(), i32> {
+    if config::current().storage != Backend::Json {
+        eprintln!("Only todos.json can be encrypted; run `ratodui migrate json` first");
+        return Err(EXIT_FAILURE);
+    }
+    if storage::locked_contents().is_some() {
+        eprintln!("ratodui: {}", crypto::CryptoError::Locked);
+        return Err(EXIT_FAILURE);
+    }
+    let storage = storage::configured().map_err(|e| {
+        eprintln!("ratodui: {}", e);
+        EXIT_FAILURE
+    })?;
+    if let Ok(None) = lock::try_lock_instance(storage.path()) {
+        eprintln!("Quit the running ratodui first");
+        return Err(EXIT_FAILURE);
+    }
+    Ok(())
+}
+
+// Read the passphrase from the first line of a file descriptor if one was
+// given, or else from the environment
+fn read_passphrase(fd: Option<&str>) -> io::Result<Option<String>> {
+    let Some(fd) = fd else {
+        return Ok(env::var(crypto::PASSPHRASE_VAR).ok());
+    };
+    let fd: u32 = fd
+        .parse()
+        .map_err(|_| io::Error::other(format!("'{}' is not a file descriptor", fd)))?;
+    let mut line = String::new();
+    BufReader::new(fs::File::open(format!("/dev/fd/{}", fd))?).read_line(&mut line)?;
+    Ok(Some(line.trim_end_matches(['\r', '\n']).to_string()))
+}
+
+// Ask on stdin which version of a todo changed on both sides to keep,
+// true for the remote one. Returns None once stdin is closed.
+fn ask_version(conflict: &Conflict) -> Option<bool> {
+    let describe = |todo: Option<&Todo>| match todo {
+        Some(todo) => {
+            let checkbox = if todo.completed { "[x]" } else { "[ ]" };
+            format!("{} {:>3}%  {}", checkbox, todo.progress, todo.name)
+        }
+        None => String::from("(removed)"),
+    };
+    println!("#{} was changed here and on the remote:", conflict.id);
+    println!("  here:   {}", describe(conflict.mine.as_ref()));
+    println!("  remote: {}", describe(conflict.theirs.as_ref()));
+
+    loop {
+        print!("Keep (m)ine or (t)heirs? ");
+        io::stdout().flush().ok()?;
+        let mut answer = String::new();
+        if io::stdin().read_line(&mut answer).ok()? == 0 {
+            println!();
+            return None;
+        }
+        match answer.trim() {
+            "m" | "mine" => return Some(false),
+            "t" | "theirs" => return Some(true),
+            _ => {}
+        }
+    }
+}
+
+// Read the todos, printing the error and returning the exit code on failure
+fn read() -> Result<Vec<Todo>, i32> {
+    read_todos().map_err(|e| {
+        eprintln!("ratodui: {}", e);
+        EXIT_FAILURE
+    })
+}
+
+// Save the todos. On failure the error message is printed and the exit code
+// returned.
+fn save(todos: &[Todo]) -> Result<(), i32> {
+    match save_todos(todos) {
+        Ok(true) => Ok(()),
+        Ok(false) => {
+            eprintln!("ratodui: the todos were changed by another program meanwhile; try again");
+            Err(EXIT_FAILURE)
+        }
+        Err(e) if e.todos_saved() => {
+            eprintln!("ratodui: saved the todos, but {}", e);
+            Ok(())
+        }
+        Err(e) => {
+            eprintln!("ratodui: failed to save todos: {}", e);
+            Err(EXIT_FAILURE)
+        }
+    }
+}
+
+// Find a todo by its ID or, if the query isn't a number, by a case-insensitive
+// name pattern. A missing ID is never taken for a pattern, so a todo that is
+// gone can't stand in for another one.
+// On failure the error message is printed and the exit code returned.
+fn find_todo(todos: &[Todo], query: &str) -> Result<usize, i32> {
+    if let Ok(id) = query.trim_start_matches('#').parse::<u64>() {
+        return todos.iter().position(|todo| todo.id == id).ok_or_else(|| {
+            eprintln!("No todo has ID {}", id);
+            EXIT_NOT_FOUND
+        });
+    }
+
+    let pattern = query.to_lowercase();
+    let matches: Vec<usize> = todos
+        .iter()
+        .enumerate()
+        .filter(|(_, todo)| !todo.completed && todo.name.to_lowercase().contains(&pattern))
+        .map(|(i, _)| i)
+        .collect();
+
+    match matches.as_slice() {
+        [i] => Ok(*i),
+        [] => {
+            eprintln!("No todo matches '{}'", query);
+            Err(EXIT_NOT_FOUND)
+        }
+        _ => {
+            eprintln!("'{}' matches more than one todo:", query);
+            for i in matches {
+                eprintln!("{:>4} {}", todos[i].id, todos[i].name);
+            }
+            Err(EXIT_AMBIGUOUS)
+        }
+    }
+}
+
+fn parse_progress(value: &str) -> Option<u16> {
+    value
+        .trim_end_matches('%')
+        .parse()
+        .ok()
+        .filter(|progress| *progress <= 100)
+}
+
+fn usage_error(message: &str) -> i32 {
+    eprintln!("ratodui: {}\n\n{}", message, USAGE);
+    EXIT_USAGE
+}
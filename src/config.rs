@@ -0,0 +1,59 @@
+// src/config.rs
+
+//! Settings kept in config.json: the storage backend and git syncing.
+
+use crate::storage::Backend;
+use directories::ProjectDirs;
+use serde::{Deserialize, Serialize};
+use std::{fs, io, path::PathBuf, sync::OnceLock};
+
+// The config as loaded on first use
+static CURRENT: OnceLock<Config> = OnceLock::new();
+
+/// Settings from config.json in the config directory
+#[derive(Serialize, Deserialize, Default)]
+pub struct Config {
+    #[serde(default)]
+    /// Where the todos are kept
+    pub storage: Backend,
+    #[serde(default)]
+    /// Commit every save to a git repository in the data directory
+    pub git: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    /// Repository `ratodui sync` pulls from and pushes to
+    pub remote: Option<String>,
+}
+
+/// The config this process started with
+pub fn current() -> &'static Config {
+    CURRENT.get_or_init(load)
+}
+
+/// Load the config, falling back to the defaults if there is none
+pub fn load() -> Config {
+    let Some(contents) = config_path().and_then(|path| fs::read_to_string(path).ok()) else {
+        return Config::default();
+    };
+    match serde_json::from_str(&contents) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Failed to parse config.json, using the defaults: {}", e);
+            Config::default()
+        }
+    }
+}
+
+/// Write the config to config.json, creating the config directory if needed.
+/// The config of a running process stays as it was loaded.
+pub fn save(config: &Config) -> io::Result<()> {
+    let path = config_path().ok_or_else(|| io::Error::other("no config directory available"))?;
+    if let Some(config_dir) = path.parent() {
+        fs::create_dir_all(config_dir)?;
+    }
+    fs::write(path, serde_json::to_string_pretty(config)?)
+}
+
+fn config_path() -> Option<PathBuf> {
+    ProjectDirs::from("com", "todo", "todo")
+        .map(|proj_dirs| proj_dirs.config_dir().join("config.json"))
+}
@@ -0,0 +1,242 @@
+// src/crypto.rs
+
+//! Optional encryption of todos.json with a passphrase.
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use chacha20poly1305::{
+    aead::{rand_core::RngCore, Aead, AeadCore, KeyInit, OsRng},
+    Key, XChaCha20Poly1305, XNonce,
+};
+use serde::{Deserialize, Serialize};
+use std::{fmt, sync::Mutex};
+
+/// Environment variable the passphrase can be given in
+pub const PASSPHRASE_VAR: &str = "RATODUI_PASSPHRASE";
+
+// Key the todos are encrypted with once unlocked, along with what it was
+// derived from, so files re-encrypted by another process can be read too
+static UNLOCKED: Mutex<Option<Unlocked>> = Mutex::new(None);
+
+struct Unlocked {
+    passphrase: String,
+    kdf: Kdf,
+    key: [u8; 32],
+}
+
+// What an encrypted todos.json holds instead of the todos: the parameters to
+// derive the key from the passphrase, and the todos.json contents encrypted
+// with XChaCha20-Poly1305, which also detects any tampering
+#[derive(Serialize, Deserialize)]
+struct Envelope {
+    encrypted: u32, // Version of this layout
+    kdf: Kdf,
+    nonce: String,
+    ciphertext: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+struct Kdf {
+    algorithm: String, // Always "argon2id" for now
+    memory: u32,       // In KiB
+    iterations: u32,
+    parallelism: u32,
+    salt: String,
+}
+
+/// Why todos.json could not be encrypted or decrypted
+#[derive(Debug)]
+pub enum CryptoError {
+    /// Encrypted, but no passphrase was given
+    Locked,
+    /// The passphrase does not open the file
+    WrongPassphrase,
+    /// The file or the key derivation is broken, with the reason
+    Invalid(String),
+}
+
+impl fmt::Display for CryptoError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CryptoError::Locked => write!(
+                f,
+                "todos.json is encrypted; give the passphrase in {} or with --passphrase-fd",
+                PASSPHRASE_VAR
+            ),
+            CryptoError::WrongPassphrase => write!(f, "wrong passphrase for todos.json"),
+            CryptoError::Invalid(e) => write!(f, "todos.json can't be decrypted: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for CryptoError {}
+
+/// Whether the contents of todos.json are encrypted
+pub fn is_encrypted(contents: &str) -> bool {
+    serde_json::from_str::<Envelope>(contents).is_ok()
+}
+
+/// Whether a passphrase has been given in this process
+pub fn is_unlocked() -> bool {
+    UNLOCKED.lock().unwrap().is_some()
+}
+
+/// Check the passphrase against encrypted contents and keep the key for
+/// reading and writing them
+pub fn unlock(contents: &str, passphrase: &str) -> Result<(), CryptoError> {
+    let envelope = parse_envelope(contents)?;
+    let unlocked = Unlocked {
+        passphrase: passphrase.to_string(),
+        key: derive_key(passphrase, &envelope.kdf)?,
+        kdf: envelope.kdf.clone(),
+    };
+    decrypt(&envelope, &unlocked.key)?;
+    *UNLOCKED.lock().unwrap() = Some(unlocked);
+    Ok(())
+}
+
+/// Encrypt everything written from now on with a new passphrase, or stop
+/// encrypting with None
+pub fn set_passphrase(passphrase: Option<&str>) -> Result<(), CryptoError> {
+    let unlocked = match passphrase {
+        Some(passphrase) => {
+            let mut salt = [0u8; 16];
+            OsRng.fill_bytes(&mut salt);
+            let params = Params::default();
+            let kdf = Kdf {
+                algorithm: String::from("argon2id"),
+                memory: params.m_cost(),
+                iterations: params.t_cost(),
+                parallelism: params.p_cost(),
+                salt: BASE64.encode(salt),
+            };
+            Some(Unlocked {
+                passphrase: passphrase.to_string(),
+                key: derive_key(passphrase, &kdf)?,
+                kdf,
+            })
+        }
+        None => None,
+    };
+    *UNLOCKED.lock().unwrap() = unlocked;
+    Ok(())
+}
+
+/// Decrypt the contents of todos.json; plain ones are returned as they are
+pub fn open(contents: &str) -> Result<String, CryptoError> {
+    if !is_encrypted(contents) {
+        return Ok(contents.to_string());
+    }
+    let envelope = parse_envelope(contents)?;
+
+    let mut unlocked = UNLOCKED.lock().unwrap();
+    let unlocked = unlocked.as_mut().ok_or(CryptoError::Locked)?;
+    if unlocked.kdf != envelope.kdf {
+        // Encrypted again since we unlocked it, with a new salt
+        unlocked.key = derive_key(&unlocked.passphrase, &envelope.kdf)?;
+        unlocked.kdf = envelope.kdf.clone();
+    }
+    decrypt(&envelope, &unlocked.key)
+}
+
+/// Encrypt new contents for todos.json if a passphrase was given, otherwise
+/// return them as they are
+pub fn seal(json: String) -> Result<String, CryptoError> {
+    let unlocked = UNLOCKED.lock().unwrap();
+    let Some(unlocked) = unlocked.as_ref() else {
+        return Ok(json);
+    };
+
+    let cipher = XChaCha20Poly1305::new(Key::from_slice(&unlocked.key));
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, json.as_bytes())
+        .map_err(|e| CryptoError::Invalid(e.to_string()))?;
+    let envelope = Envelope {
+        encrypted: 1,
+        kdf: unlocked.kdf.clone(),
+        nonce: BASE64.encode(nonce),
+        ciphertext: BASE64.encode(ciphertext),
+    };
+    serde_json::to_string_pretty(&envelope).map_err(|e| CryptoError::Invalid(e.to_string()))
+}
+
+fn parse_envelope(contents: &str) -> Result<Envelope, CryptoError> {
+    let envelope: Envelope =
+        serde_json::from_str(contents).map_err(|e| CryptoError::Invalid(e.to_string()))?;
+    if envelope.encrypted != 1 || envelope.kdf.algorithm != "argon2id" {
+        return Err(CryptoError::Invalid(String::from(
+            "encrypted by a newer version of ratodui",
+        )));
+    }
+    Ok(envelope)
+}
+
+fn derive_key(passphrase: &str, kdf: &Kdf) -> Result<[u8; 32], CryptoError> {
+    let invalid = |e: argon2::Error| CryptoError::Invalid(e.to_string());
+    let salt = BASE64
+        .decode(&kdf.salt)
+        .map_err(|e| CryptoError::Invalid(e.to_string()))?;
+    let params =
+        Params::new(kdf.memory, kdf.iterations, kdf.parallelism, Some(32)).map_err(invalid)?;
+    let mut key = [0u8; 32];
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase.as_bytes(), &salt, &mut key)
+        .map_err(invalid)?;
+    Ok(key)
+}
+
+fn decrypt(envelope: &Envelope, key: &[u8; 32]) -> Result<String, CryptoError> {
+    let decode = |text: &str| {
+        BASE64
+            .decode(text)
+            .map_err(|e| CryptoError::Invalid(e.to_string()))
+    };
+    let nonce = decode(&envelope.nonce)?;
+    if nonce.len() != 24 {
+        return Err(CryptoError::Invalid(String::from("bad nonce")));
+    }
+    let cipher = XChaCha20Poly1305::new(Key::from_slice(key));
+    // The authentication tag fails to match with the wrong key
+    let json = cipher
+        .decrypt(
+            XNonce::from_slice(&nonce),
+            decode(&envelope.ciphertext)?.as_slice(),
+        )
+        .map_err(|_| CryptoError::WrongPassphrase)?;
+    String::from_utf8(json).map_err(|e| CryptoError::Invalid(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_contents_are_opened_as_they_are() {
+        let json = r#"{"version": 2, "lists": []}"#;
+        assert!(!is_encrypted(json));
+        assert_eq!(open(json).unwrap(), json);
+    }
+
+    // One test, as the passphrase is kept for the whole process
+    #[test]
+    fn sealed_contents_open_with_the_passphrase_only() {
+        let json = String::from(r#"{"version": 2, "lists": []}"#);
+        set_passphrase(Some("correct horse")).unwrap();
+        let sealed = seal(json.clone()).unwrap();
+        assert!(is_encrypted(&sealed));
+        assert!(!sealed.contains("lists"));
+        assert_eq!(open(&sealed).unwrap(), json);
+
+        assert!(matches!(
+            unlock(&sealed, "battery staple"),
+            Err(CryptoError::WrongPassphrase)
+        ));
+        unlock(&sealed, "correct horse").unwrap();
+        assert_eq!(open(&sealed).unwrap(), json);
+
+        set_passphrase(None).unwrap();
+        assert!(matches!(open(&sealed), Err(CryptoError::Locked)));
+        assert_eq!(seal(json.clone()).unwrap(), json);
+    }
+}
@@ -0,0 +1,217 @@
+// src/csv.rs
+
+use crate::{recurrence::Recurrence, Todo};
+use chrono::{DateTime, NaiveDate, Utc};
+
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+const HEADER: &[&str] = &[
+    "id",
+    "name",
+    "progress",
+    "completed",
+    "archived",
+    "created_at",
+    "completed_at",
+    "due",
+    "priority",
+    "tags",
+    "recurrence",
+    "blocked_by",
+    "notes",
+    "time_spent_hours",
+];
+
+// Export the todos followed by the archived ones as CSV with a header row.
+// Fields are quoted where needed, so notes may span several lines.
+pub fn export(todos: &[Todo], archived: &[Todo]) -> String {
+    let mut out = export_row(HEADER.iter().map(|column| column.to_string()));
+    for todo in todos {
+        out.push_str(&export_row(todo_fields(todo, false)));
+    }
+    for todo in archived {
+        out.push_str(&export_row(todo_fields(todo, true)));
+    }
+    out
+}
+
+// Import todos from CSV. Columns are matched by their header, ignoring case,
+// so files from other tools work as long as they have a name column;
+// unknown columns are skipped. Computed columns like archived or
+// time_spent_hours are ignored.
+pub fn import(contents: &str) -> Vec<Todo> {
+    let mut rows = parse(contents).into_iter();
+    let Some(header) = rows.next() else {
+        return Vec::new();
+    };
+    let columns: Vec<String> = header
+        .iter()
+        .map(|column| column.trim().to_lowercase())
+        .collect();
+
+    rows.filter(|row| row.iter().any(|field| !field.trim().is_empty()))
+        .map(|row| {
+            let mut todo = Todo::new("");
+            todo.created_at = None;
+            for (column, value) in columns.iter().zip(&row) {
+                import_field(&mut todo, column, value.trim());
+            }
+            todo
+        })
+        .collect()
+}
+
+fn todo_fields(todo: &Todo, archived: bool) -> Vec<String> {
+    let ids: Vec<String> = todo.blocked_by.iter().map(|id| id.to_string()).collect();
+    vec![
+        todo.id.to_string(),
+        todo.name.clone(),
+        todo.progress.to_string(),
+        todo.completed.to_string(),
+        archived.to_string(),
+        todo.created_at
+            .map(|at| at.to_rfc3339())
+            .unwrap_or_default(),
+        todo.completed_at
+            .map(|at| at.to_rfc3339())
+            .unwrap_or_default(),
+        todo.due
+            .map(|due| due.format(DATE_FORMAT).to_string())
+            .unwrap_or_default(),
+        todo.priority.map(String::from).unwrap_or_default(),
+        todo.tags.join(" "),
+        todo.recurrence
+            .as_ref()
+            .map(|recurrence| recurrence.to_string())
+            .unwrap_or_default(),
+        ids.join(" "),
+        todo.notes.clone(),
+        time_spent(todo)
+            .map(|hours| format!("{:.1}", hours))
+            .unwrap_or_default(),
+    ]
+}
+
+// Hours from the first progress entry until the todo was completed, or until
+// the last progress entry while it is still open
+fn time_spent(todo: &Todo) -> Option<f64> {
+    let start = todo.history.first()?.at;
+    let end = match (todo.completed, todo.completed_at) {
+        (true, Some(completed_at)) => completed_at,
+        _ => todo.history.last()?.at,
+    };
+    Some((end - start).num_minutes().max(0) as f64 / 60.0)
+}
+
+fn import_field(todo: &mut Todo, column: &str, value: &str) {
+    if value.is_empty() {
+        return;
+    }
+    match column {
+        "id" => todo.id = value.trim_start_matches('#').parse().unwrap_or(0),
+        "name" | "title" | "summary" | "task" => todo.name = value.to_string(),
+        "progress" | "percent" | "percent_complete" => {
+            if let Ok(progress) = value.trim_end_matches('%').trim().parse::<u16>() {
+                todo.progress = progress.min(100);
+            }
+        }
+        "completed" | "done" | "status" => todo.completed = parse_bool(value),
+        "created_at" | "created" => todo.created_at = parse_date_time(value),
+        "completed_at" => todo.completed_at = parse_date_time(value),
+        "due" | "due_date" => todo.due = parse_date_time(value).map(|at| at.date_naive()),
+        "priority" => {
+            todo.priority = value
+                .chars()
+                .next()
+                .map(|c| c.to_ascii_uppercase())
+                .filter(|c| c.is_ascii_uppercase());
+        }
+        "tags" => {
+            for tag in value.split([' ', ',']).filter(|tag| !tag.is_empty()) {
+                if !todo.tags.iter().any(|existing| existing == tag) {
+                    todo.tags.push(tag.to_string());
+                }
+            }
+        }
+        "recurrence" | "repeats" => todo.recurrence = Recurrence::parse(value),
+        "blocked_by" => {
+            todo.blocked_by = value
+                .split([' ', ','])
+                .filter_map(|id| id.trim_start_matches('#').parse().ok())
+                .collect();
+        }
+        "notes" | "description" => todo.notes = value.to_string(),
+        _ => {}
+    }
+}
+
+fn parse_bool(value: &str) -> bool {
+    matches!(
+        value.to_lowercase().as_str(),
+        "true" | "yes" | "y" | "1" | "x" | "done" | "completed"
+    )
+}
+
+// Accept RFC 3339 timestamps as exported, or plain dates
+fn parse_date_time(value: &str) -> Option<DateTime<Utc>> {
+    if let Ok(date_time) = DateTime::parse_from_rfc3339(value) {
+        return Some(date_time.with_timezone(&Utc));
+    }
+    let date = NaiveDate::parse_from_str(value, DATE_FORMAT).ok()?;
+    Some(date.and_hms_opt(0, 0, 0)?.and_utc())
+}
+
+// Join fields into a CRLF terminated row, quoting the ones that contain a
+// separator, a quote or a line break and doubling the quotes inside them
+fn export_row(fields: impl IntoIterator<Item = String>) -> String {
+    let fields: Vec<String> = fields
+        .into_iter()
+        .map(|field| {
+            if field.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field
+            }
+        })
+        .collect();
+    format!("{}\r\n", fields.join(","))
+}
+
+// Split CSV into rows of fields. Quoted fields may contain separators,
+// doubled quotes and line breaks.
+fn parse(contents: &str) -> Vec<Vec<String>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = contents.trim_start_matches('\u{feff}').chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if in_quotes {
+            match c {
+                '"' if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                '"' => in_quotes = false,
+                _ => field.push(c),
+            }
+            continue;
+        }
+        match c {
+            '"' => in_quotes = true,
+            ',' => row.push(std::mem::take(&mut field)),
+            '\r' => {}
+            '\n' => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+            }
+            _ => field.push(c),
+        }
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+    rows
+}
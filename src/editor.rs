@@ -0,0 +1,167 @@
+// src/editor.rs
+
+use ratatui::{
+    backend::Backend,
+    layout::Rect,
+    text::Spans,
+    widgets::{Block, Paragraph},
+};
+
+// A minimal multi-line text editor used for todo notes
+pub struct TextEditor {
+    text: String,
+    cursor: usize, // Byte offset into `text`, always on a char boundary
+}
+
+impl TextEditor {
+    pub fn new(text: &str) -> Self {
+        TextEditor {
+            text: text.to_string(),
+            cursor: text.len(), // Start editing at the end of the text
+        }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn insert(&mut self, c: char) {
+        self.text.insert(self.cursor, c);
+        self.cursor += c.len_utf8();
+    }
+
+    pub fn backspace(&mut self) {
+        if let Some(c) = self.text[..self.cursor].chars().next_back() {
+            self.cursor -= c.len_utf8();
+            self.text.remove(self.cursor);
+        }
+    }
+
+    pub fn delete(&mut self) {
+        if self.cursor < self.text.len() {
+            self.text.remove(self.cursor);
+        }
+    }
+
+    pub fn move_left(&mut self) {
+        if let Some(c) = self.text[..self.cursor].chars().next_back() {
+            self.cursor -= c.len_utf8();
+        }
+    }
+
+    pub fn move_right(&mut self) {
+        if let Some(c) = self.text[self.cursor..].chars().next() {
+            self.cursor += c.len_utf8();
+        }
+    }
+
+    pub fn move_home(&mut self) {
+        self.cursor = self.line_start(self.cursor);
+    }
+
+    pub fn move_end(&mut self) {
+        self.cursor = self.line_end(self.cursor);
+    }
+
+    pub fn move_up(&mut self) {
+        let start = self.line_start(self.cursor);
+        if start == 0 {
+            self.cursor = 0;
+            return;
+        }
+        let column = self.text[start..self.cursor].chars().count();
+        let previous_start = self.line_start(start - 1);
+        self.cursor = self.offset_in_line(previous_start, column);
+    }
+
+    pub fn move_down(&mut self) {
+        let end = self.line_end(self.cursor);
+        if end == self.text.len() {
+            self.cursor = end;
+            return;
+        }
+        let column = self.text[self.line_start(self.cursor)..self.cursor]
+            .chars()
+            .count();
+        self.cursor = self.offset_in_line(end + 1, column);
+    }
+
+    // Render the text wrapped to the area, scrolled so the cursor stays visible
+    pub fn render<B: Backend>(&self, f: &mut ratatui::Frame<B>, block: Block, area: Rect) {
+        let inner = block.inner(area);
+        let rows = wrap_rows(&self.text, inner.width as usize);
+
+        // Find the visual row and column of the cursor
+        let cursor_row = rows
+            .iter()
+            .rposition(|&(start, _)| start <= self.cursor)
+            .unwrap_or(0);
+        let (row_start, row_end) = rows[cursor_row];
+        let cursor_col = self.text[row_start..self.cursor.min(row_end)]
+            .chars()
+            .count();
+
+        let height = inner.height.max(1) as usize;
+        let scroll = cursor_row.saturating_sub(height - 1);
+
+        let lines: Vec<Spans> = rows
+            .iter()
+            .skip(scroll)
+            .take(height)
+            .map(|&(start, end)| Spans::from(self.text[start..end].to_string()))
+            .collect();
+        f.render_widget(Paragraph::new(lines).block(block), area);
+
+        if inner.width > 0 && inner.height > 0 {
+            f.set_cursor(
+                inner.x + (cursor_col as u16).min(inner.width - 1),
+                inner.y + (cursor_row - scroll) as u16,
+            );
+        }
+    }
+
+    fn line_start(&self, offset: usize) -> usize {
+        self.text[..offset].rfind('\n').map_or(0, |i| i + 1)
+    }
+
+    fn line_end(&self, offset: usize) -> usize {
+        self.text[offset..]
+            .find('\n')
+            .map_or(self.text.len(), |i| offset + i)
+    }
+
+    // Byte offset of the given column in the line starting at `start`,
+    // clamped to the end of that line
+    fn offset_in_line(&self, start: usize, column: usize) -> usize {
+        let end = self.line_end(start);
+        self.text[start..end]
+            .char_indices()
+            .nth(column)
+            .map_or(end, |(i, _)| start + i)
+    }
+}
+
+// Split text into visual rows of at most `width` chars, returning byte ranges.
+// Lines are broken at newlines and hard-wrapped when they exceed the width.
+pub fn wrap_rows(text: &str, width: usize) -> Vec<(usize, usize)> {
+    let width = width.max(1);
+    let mut rows = Vec::new();
+    let mut line_start = 0;
+
+    for line in text.split('\n') {
+        let mut row_start = line_start;
+        let mut count = 0;
+        for (i, _) in line.char_indices() {
+            if count == width {
+                rows.push((row_start, line_start + i));
+                row_start = line_start + i;
+                count = 0;
+            }
+            count += 1;
+        }
+        rows.push((row_start, line_start + line.len()));
+        line_start += line.len() + 1;
+    }
+
+    rows
+}
@@ -0,0 +1,75 @@
+// src/formats.rs
+
+//! Import and export of todos in the formats of other todo tools.
+
+use crate::{csv, ical, markdown, next_id, org, taskwarrior, todotxt, Todo};
+
+/// File formats todos can be imported from and exported to
+pub const FORMATS: &[&str] = &["todotxt", "markdown", "ical", "csv"];
+
+/// Formats of other tools that todos can only be imported from
+pub const IMPORT_FORMATS: &[&str] = &["taskwarrior", "org"];
+
+/// Export the todos in a format. Only CSV, which is meant for reporting,
+/// includes the archived todos.
+pub fn export(format: &str, todos: &[Todo], archived: &[Todo]) -> Result<String, String> {
+    match format {
+        "todotxt" => Ok(todotxt::export(todos)),
+        "markdown" | "md" => Ok(markdown::export(todos)),
+        "ical" | "ics" => Ok(ical::export(todos)),
+        "csv" => Ok(csv::export(todos, archived)),
+        "taskwarrior" | "task" | "org" => Err(format!("'{}' can only be imported", format)),
+        _ => Err(unknown_format(format)),
+    }
+}
+
+/// Read todos from the contents of a file in a format. Their IDs and blockers
+/// are those of the file until they are added to the list with [`merge`].
+pub fn import(format: &str, contents: &str) -> Result<Vec<Todo>, String> {
+    match format {
+        "todotxt" => Ok(todotxt::import(contents)),
+        "markdown" | "md" => Ok(markdown::import(contents)),
+        "ical" | "ics" => Ok(ical::import(contents)),
+        "csv" => Ok(csv::import(contents)),
+        "taskwarrior" | "task" => taskwarrior::import(contents),
+        "org" => Ok(org::import(contents)),
+        _ => Err(unknown_format(format)),
+    }
+}
+
+/// Add imported todos to the list under new IDs. Blocker references between
+/// imported todos are kept, references to anything else are dropped.
+/// Returns the number of todos added.
+pub fn merge(todos: &mut Vec<Todo>, imported: Vec<Todo>) -> usize {
+    let count = imported.len();
+    let mut id_map = Vec::new();
+    let first = todos.len();
+
+    for mut todo in imported {
+        let new_id = next_id(todos);
+        if todo.id != 0 {
+            id_map.push((todo.id, new_id));
+        }
+        todo.id = new_id;
+        todos.push(todo);
+    }
+
+    for todo in &mut todos[first..] {
+        todo.blocked_by = todo
+            .blocked_by
+            .iter()
+            .filter_map(|old| id_map.iter().find(|(id, _)| id == old).map(|(_, new)| *new))
+            .collect();
+    }
+
+    count
+}
+
+fn unknown_format(format: &str) -> String {
+    format!(
+        "unknown format '{}' (expected one of: {}, or {} for import)",
+        format,
+        FORMATS.join(", "),
+        IMPORT_FORMATS.join(", ")
+    )
+}
@@ -0,0 +1,293 @@
+// src/git.rs
+
+//! Syncing the todos with a git remote.
+
+use crate::{config, crypto, merge, merge::Conflict, schema, storage, storage::Backend, Todo};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+// Branch the todos are committed to, locally and on the remote
+const BRANCH: &str = "main";
+
+// Lock files, temporary files and backups stay out of the repository
+const GITIGNORE: &str = "*.lock\n*.tmp\n*.bak\n";
+
+/// How a sync went
+pub enum Synced {
+    /// Both sides had the same todos
+    UpToDate,
+    /// Only our todos had changed, and they were pushed
+    Pushed,
+    /// Only the remote todos had changed, and they were taken
+    Pulled,
+    /// Both had changed, and the merge was pushed
+    Merged {
+        /// Todos changed in different ways on both sides, settled by the user
+        conflicts: usize,
+    },
+}
+
+/// Whether saves are committed. Only todos.json can be diffed and merged, so
+/// git mode does nothing with the SQLite storage.
+pub fn is_enabled() -> bool {
+    let config = config::current();
+    config.git && config.storage == Backend::Json
+}
+
+/// Commit the data file after a save, with a message describing how the
+/// todos changed. Saves that didn't change any todo go with the next commit.
+pub fn commit_save(data_file: &Path, todos: &[Todo], archived: &[Todo]) -> Result<(), String> {
+    let repo = Repo::open(data_file)?;
+    let (before, _) = repo.todos_at("HEAD").unwrap_or_default();
+    if let Some(message) = describe(&before, todos, archived) {
+        repo.commit(&message)?;
+    }
+    Ok(())
+}
+
+/// Pull the todos from the remote, merge them with ours todo by todo and push
+/// the result. `resolve` picks the version to keep of each todo changed on
+/// both sides, true for theirs, or gives up with None before anything changed.
+pub fn sync(
+    data_file: &Path,
+    remote: &str,
+    mut resolve: impl FnMut(&Conflict) -> Option<bool>,
+) -> Result<Synced, String> {
+    let repo = Repo::open(data_file)?;
+    repo.commit("Save todos")?; // Changes from before git mode was turned on
+
+    match repo.git(&["remote", "get-url", "origin"]) {
+        Ok(url) if url == remote => {}
+        Ok(_) => {
+            repo.git(&["remote", "set-url", "origin", remote])?;
+        }
+        Err(_) => {
+            repo.git(&["remote", "add", "origin", remote])?;
+        }
+    }
+    repo.git(&["fetch", "--quiet", "origin"])?;
+
+    let upstream = format!("origin/{}", BRANCH);
+    let local = repo.git(&["rev-parse", "--verify", "--quiet", "HEAD"]).ok();
+    let remote_head = repo
+        .git(&["rev-parse", "--verify", "--quiet", &upstream])
+        .ok();
+    let synced = match (local, remote_head) {
+        (None, None) => return Ok(Synced::UpToDate),
+        (Some(local), Some(remote_head)) if local == remote_head => return Ok(Synced::UpToDate),
+        (_, None) => Synced::Pushed,
+        (None, Some(_)) => {
+            repo.git(&["merge", "--quiet", "--ff-only", &upstream])?;
+            return Ok(Synced::Pulled);
+        }
+        (Some(_), Some(_)) if repo.is_ancestor(&upstream, "HEAD") => Synced::Pushed,
+        (Some(_), Some(_)) if repo.is_ancestor("HEAD", &upstream) => {
+            repo.git(&["merge", "--quiet", "--ff-only", &upstream])?;
+            return Ok(Synced::Pulled);
+        }
+        (Some(_), Some(_)) => repo.merge(&upstream, remote, &mut resolve)?,
+    };
+
+    repo.git(&["push", "--quiet", "origin", &format!("HEAD:{}", BRANCH)])
+        .map_err(|e| format!("{}; sync again to merge the latest changes", e))?;
+    Ok(synced)
+}
+
+// The git repository in the data directory
+struct Repo {
+    dir: PathBuf,
+    file_name: String, // Name of the data file in the repository
+    identity: bool,    // Whether git knows who to commit as
+}
+
+impl Repo {
+    // Open the repository, creating it on first use
+    fn open(data_file: &Path) -> Result<Repo, String> {
+        let dir = data_file.parent().ok_or("no data directory available")?;
+        let file_name = data_file.file_name().ok_or("no data file")?;
+        let mut repo = Repo {
+            dir: dir.to_path_buf(),
+            file_name: file_name.to_string_lossy().into_owned(),
+            identity: true,
+        };
+
+        if !repo.dir.join(".git").exists() {
+            fs::create_dir_all(&repo.dir).map_err(|e| e.to_string())?;
+            repo.git(&["init", "--quiet", "--initial-branch", BRANCH])?;
+            fs::write(repo.dir.join(".gitignore"), GITIGNORE).map_err(|e| e.to_string())?;
+        }
+        repo.identity = repo.git(&["config", "user.email"]).is_ok();
+        Ok(repo)
+    }
+
+    // Run git in the repository and return what it printed
+    fn git(&self, args: &[&str]) -> Result<String, String> {
+        let mut command = Command::new("git");
+        command.arg("-C").arg(&self.dir);
+        if !self.identity {
+            // Commit as ratodui where no identity is configured
+            command.args([
+                "-c",
+                "user.name=ratodui",
+                "-c",
+                "user.email=ratodui@localhost",
+            ]);
+        }
+        let output = command
+            .args(args)
+            .output()
+            .map_err(|e| format!("failed to run git: {}", e))?;
+        if !output.status.success() {
+            let error = String::from_utf8_lossy(&output.stderr);
+            return Err(format!("git {} failed: {}", args[0], error.trim()));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+
+    // Commit everything that changed. Returns false if there was nothing to commit.
+    fn commit(&self, message: &str) -> Result<bool, String> {
+        self.git(&["add", "--all"])?;
+        let merging = self.dir.join(".git").join("MERGE_HEAD").exists();
+        if !merging && self.git(&["diff", "--cached", "--quiet"]).is_ok() {
+            return Ok(false);
+        }
+        self.git(&["commit", "--quiet", "--no-verify", "-m", message])?;
+        Ok(true)
+    }
+
+    fn is_ancestor(&self, ancestor: &str, descendant: &str) -> bool {
+        self.git(&["merge-base", "--is-ancestor", ancestor, descendant])
+            .is_ok()
+    }
+
+    // The todos and archived todos in the data file at a revision; none if
+    // the file isn't there
+    fn todos_at(&self, revision: &str) -> Result<(Vec<Todo>, Vec<Todo>), String> {
+        let path = format!("{}:{}", revision, self.file_name);
+        let Ok(contents) = self.git(&["show", &path]) else {
+            return Ok((Vec::new(), Vec::new()));
+        };
+        let contents = crypto::open(&contents).map_err(|e| e.to_string())?;
+        let parse_error = |e: schema::SchemaError| format!("{} in {}", e, revision);
+        let (todos, _) = schema::parse(&contents).map_err(parse_error)?;
+        let archived = schema::parse_archive(&contents).map_err(parse_error)?;
+        // A merge must not give out IDs either side already used
+        storage::remember_next_id(schema::next_id(&contents));
+        Ok((todos, archived))
+    }
+
+    // Merge the todos of `upstream` into ours one todo at a time, rather
+    // than line by line, and commit the result as a merge
+    fn merge(
+        &self,
+        upstream: &str,
+        remote: &str,
+        resolve: &mut impl FnMut(&Conflict) -> Option<bool>,
+    ) -> Result<Synced, String> {
+        // Histories started on two machines have nothing in common
+        let (base, _) = match self.git(&["merge-base", "HEAD", upstream]) {
+            Ok(base_revision) => self.todos_at(&base_revision)?,
+            Err(_) => Default::default(),
+        };
+        let (mine, my_archive) = self.todos_at("HEAD")?;
+        let (theirs, their_archive) = self.todos_at(upstream)?;
+
+        let mut merged = merge::merge(&base, &mine, &theirs);
+        let conflicts = merged.conflicts.len();
+        for conflict in std::mem::take(&mut merged.conflicts) {
+            let Some(take_theirs) = resolve(&conflict) else {
+                return Err(String::from("cancelled, nothing was changed"));
+            };
+            merge::resolve(&mut merged.todos, conflict, take_theirs);
+        }
+        // The archive only grows, so take what they archived that we haven't
+        let archived: Vec<Todo> = their_archive
+            .into_iter()
+            .filter(|todo| !my_archive.contains(todo))
+            .collect();
+
+        // Start a merge that keeps our file, then put the merged todos in it
+        self.git(&[
+            "merge",
+            "--quiet",
+            "--no-ff",
+            "--no-commit",
+            "--allow-unrelated-histories",
+            "--strategy",
+            "ours",
+            upstream,
+        ])?;
+        let file_path = self.dir.join(&self.file_name);
+        let existing = match fs::read_to_string(&file_path) {
+            Ok(contents) => Some(crypto::open(&contents).map_err(|e| e.to_string())?),
+            Err(_) => None,
+        };
+        let json = schema::update(
+            existing.as_deref(),
+            &merged.todos,
+            &archived,
+            storage::next_free_id(),
+        )
+        .map_err(|e| e.to_string())?;
+        let json = crypto::seal(json).map_err(|e| e.to_string())?;
+        let temp_path = file_path.with_extension("json.tmp");
+        fs::write(&temp_path, json)
+            .and_then(|()| fs::rename(&temp_path, &file_path))
+            .map_err(|e| e.to_string())?;
+        self.commit(&format!("Merge todos from {}", remote))?;
+        Ok(Synced::Merged { conflicts })
+    }
+}
+
+// Commit message for a save: what happened to the todos, with a summary
+// line followed by one line per change when there are several. None if no
+// todo changed.
+fn describe(before: &[Todo], after: &[Todo], archived: &[Todo]) -> Option<String> {
+    let mut changes = Vec::new();
+    for todo in after {
+        match before.iter().find(|old| old.id == todo.id) {
+            None => changes.push(format!("Add \"{}\"", todo.name)),
+            Some(old) if old == todo => {}
+            Some(old) if old.completed != todo.completed => {
+                let verb = if todo.completed { "Complete" } else { "Reopen" };
+                changes.push(format!("{} \"{}\"", verb, todo.name));
+            }
+            Some(old) if old.name != todo.name => {
+                changes.push(format!("Rename \"{}\" to \"{}\"", old.name, todo.name));
+            }
+            Some(old) if old.progress != todo.progress => {
+                changes.push(format!("Set \"{}\" to {}%", todo.name, todo.progress));
+            }
+            Some(_) => changes.push(format!("Edit \"{}\"", todo.name)),
+        }
+    }
+    for old in before {
+        if !after.iter().any(|todo| todo.id == old.id) {
+            let verb = if archived.iter().any(|todo| todo.id == old.id) {
+                "Archive"
+            } else {
+                "Remove"
+            };
+            changes.push(format!("{} \"{}\"", verb, old.name));
+        }
+    }
+
+    match changes.as_slice() {
+        [] => None,
+        [change] => Some(change.clone()),
+        _ => {
+            let lines: Vec<String> = changes
+                .iter()
+                .map(|change| format!("- {}", change))
+                .collect();
+            Some(format!(
+                "Update {} todos\n\n{}",
+                changes.len(),
+                lines.join("\n")
+            ))
+        }
+    }
+}
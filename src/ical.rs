@@ -0,0 +1,386 @@
+// src/ical.rs
+
+use crate::{recurrence::Recurrence, Todo};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc, Weekday};
+
+const DATE_FORMAT: &str = "%Y%m%d";
+const DATE_TIME_FORMAT: &str = "%Y%m%dT%H%M%S";
+
+// Export todos as an iCalendar file with one VTODO component per todo
+pub fn export(todos: &[Todo]) -> String {
+    let mut lines = vec![
+        String::from("BEGIN:VCALENDAR"),
+        String::from("VERSION:2.0"),
+        String::from("PRODID:-//ratodui//ratodui//EN"),
+    ];
+    let now = Utc::now();
+
+    for todo in todos {
+        lines.push(String::from("BEGIN:VTODO"));
+        lines.push(format!("UID:{}", uid(todo.id)));
+        lines.push(format!("DTSTAMP:{}", format_date_time(now)));
+        if let Some(created_at) = todo.created_at {
+            lines.push(format!("CREATED:{}", format_date_time(created_at)));
+        }
+        lines.push(format!("SUMMARY:{}", escape(&todo.name)));
+        if !todo.notes.is_empty() {
+            lines.push(format!("DESCRIPTION:{}", escape(&todo.notes)));
+        }
+
+        let status = if todo.completed {
+            "COMPLETED"
+        } else if todo.progress > 0 {
+            "IN-PROCESS"
+        } else {
+            "NEEDS-ACTION"
+        };
+        lines.push(format!("STATUS:{}", status));
+        lines.push(format!("PERCENT-COMPLETE:{}", todo.progress));
+        if let Some(completed_at) = todo.completed_at {
+            lines.push(format!("COMPLETED:{}", format_date_time(completed_at)));
+        }
+
+        if let Some(due) = todo.due {
+            lines.push(format!("DUE;VALUE=DATE:{}", due.format(DATE_FORMAT)));
+        }
+        if let Some(priority) = todo.priority {
+            lines.push(format!("PRIORITY:{}", priority_to_ical(priority)));
+        }
+        if !todo.tags.is_empty() {
+            let categories: Vec<String> = todo
+                .tags
+                .iter()
+                .map(|tag| escape(tag.trim_start_matches(['+', '@'])))
+                .collect();
+            lines.push(format!("CATEGORIES:{}", categories.join(",")));
+        }
+        match &todo.recurrence {
+            Some(Recurrence::AfterCompletion { days }) => {
+                // iCalendar has no rule relative to completion
+                lines.push(format!("X-RATODUI-AFTER-COMPLETION:{}", days));
+            }
+            Some(recurrence) => lines.push(format!("RRULE:{}", rrule(recurrence))),
+            None => {}
+        }
+        for id in &todo.blocked_by {
+            lines.push(format!("RELATED-TO;RELTYPE=DEPENDS-ON:{}", uid(*id)));
+        }
+        lines.push(String::from("END:VTODO"));
+    }
+    lines.push(String::from("END:VCALENDAR"));
+
+    let mut out = String::new();
+    for line in lines {
+        out.push_str(&fold(&line));
+    }
+    out
+}
+
+// Import all VTODO components from an iCalendar file
+pub fn import(contents: &str) -> Vec<Todo> {
+    let mut todos = Vec::new();
+    let mut current: Option<Todo> = None;
+
+    for line in unfold(contents) {
+        let Some((name_and_params, value)) = line.split_once(':') else {
+            continue;
+        };
+        let mut params = name_and_params.split(';');
+        let name = params.next().unwrap_or("").to_ascii_uppercase();
+        let params: Vec<&str> = params.collect();
+
+        match (name.as_str(), value) {
+            ("BEGIN", "VTODO") => {
+                let mut todo = Todo::new("");
+                todo.created_at = None;
+                current = Some(todo);
+            }
+            ("END", "VTODO") => {
+                if let Some(mut todo) = current.take() {
+                    restore_tag_prefixes(&mut todo);
+                    todos.push(todo);
+                }
+            }
+            _ => {
+                if let Some(todo) = current.as_mut() {
+                    import_property(todo, &name, &params, value);
+                }
+            }
+        }
+    }
+
+    todos
+}
+
+fn import_property(todo: &mut Todo, name: &str, params: &[&str], value: &str) {
+    match name {
+        "UID" => {
+            if let Some(id) = value
+                .strip_prefix("ratodui-")
+                .and_then(|id| id.parse().ok())
+            {
+                todo.id = id;
+            }
+        }
+        "SUMMARY" => todo.name = unescape(value),
+        "DESCRIPTION" => todo.notes = unescape(value),
+        "STATUS" => todo.completed = value.eq_ignore_ascii_case("COMPLETED"),
+        "PERCENT-COMPLETE" => {
+            if let Ok(progress) = value.trim().parse::<u16>() {
+                todo.progress = progress.min(100);
+            }
+        }
+        "CREATED" => todo.created_at = parse_date_time(value),
+        "COMPLETED" => todo.completed_at = parse_date_time(value),
+        "DUE" => todo.due = parse_date(value),
+        "PRIORITY" => todo.priority = priority_from_ical(value),
+        "CATEGORIES" => {
+            for category in split_list(value) {
+                if !category.is_empty() && !todo.tags.contains(&category) {
+                    todo.tags.push(category);
+                }
+            }
+        }
+        "RRULE" => todo.recurrence = parse_rrule(value),
+        "X-RATODUI-AFTER-COMPLETION" => {
+            if let Ok(days) = value.parse() {
+                todo.recurrence = Some(Recurrence::AfterCompletion { days });
+            }
+        }
+        "RELATED-TO" if params.contains(&"RELTYPE=DEPENDS-ON") => {
+            if let Some(id) = value
+                .strip_prefix("ratodui-")
+                .and_then(|id| id.parse().ok())
+            {
+                todo.blocked_by.push(id);
+            }
+        }
+        _ => {}
+    }
+}
+
+// Categories are exported without the "+" or "@" of projects and contexts.
+// Put it back when the tag also appears in the summary with one.
+fn restore_tag_prefixes(todo: &mut Todo) {
+    for tag in &mut todo.tags {
+        for prefix in ['+', '@'] {
+            let prefixed = format!("{}{}", prefix, tag);
+            if todo.name.split_whitespace().any(|word| word == prefixed) {
+                *tag = prefixed;
+                break;
+            }
+        }
+    }
+}
+
+fn uid(id: u64) -> String {
+    format!("ratodui-{}", id)
+}
+
+// Priorities A to I map to the iCalendar priorities 1 (highest) to 9
+fn priority_to_ical(priority: char) -> u8 {
+    (priority as u8).saturating_sub(b'A').min(8) + 1
+}
+
+fn priority_from_ical(value: &str) -> Option<char> {
+    match value.trim().parse::<u8>() {
+        Ok(priority @ 1..=9) => Some((b'A' + priority - 1) as char),
+        _ => None, // 0 means undefined
+    }
+}
+
+fn rrule(recurrence: &Recurrence) -> String {
+    match recurrence {
+        Recurrence::Day => String::from("FREQ=DAILY"),
+        Recurrence::Weekday => String::from("FREQ=WEEKLY;BYDAY=MO,TU,WE,TH,FR"),
+        Recurrence::Week { days } if days.is_empty() => String::from("FREQ=WEEKLY"),
+        Recurrence::Week { days } => {
+            let days: Vec<&str> = days.iter().map(|day| weekday_code(*day)).collect();
+            format!("FREQ=WEEKLY;BYDAY={}", days.join(","))
+        }
+        Recurrence::Month => String::from("FREQ=MONTHLY"),
+        Recurrence::Days { days } | Recurrence::AfterCompletion { days } => {
+            format!("FREQ=DAILY;INTERVAL={}", days)
+        }
+    }
+}
+
+fn parse_rrule(value: &str) -> Option<Recurrence> {
+    let mut freq = None;
+    let mut interval = 1;
+    let mut days = Vec::new();
+    for part in value.split(';') {
+        match part.split_once('=')? {
+            ("FREQ", f) => freq = Some(f),
+            ("INTERVAL", i) => interval = i.parse().ok()?,
+            ("BYDAY", list) => {
+                days = list
+                    .split(',')
+                    .map(weekday_from_code)
+                    .collect::<Option<Vec<_>>>()?;
+            }
+            _ => {}
+        }
+    }
+
+    let weekdays = [
+        Weekday::Mon,
+        Weekday::Tue,
+        Weekday::Wed,
+        Weekday::Thu,
+        Weekday::Fri,
+    ];
+    let recurrence = match (freq?, interval) {
+        ("DAILY", 1) => Recurrence::Day,
+        ("DAILY", days) => Recurrence::Days { days },
+        ("WEEKLY", 1) if days == weekdays => Recurrence::Weekday,
+        ("WEEKLY", 1) => Recurrence::Week { days },
+        ("WEEKLY", weeks) => Recurrence::Days {
+            days: weeks.checked_mul(7)?,
+        },
+        ("MONTHLY", _) => Recurrence::Month,
+        _ => return None,
+    };
+    Some(recurrence)
+}
+
+fn weekday_code(day: Weekday) -> &'static str {
+    match day {
+        Weekday::Mon => "MO",
+        Weekday::Tue => "TU",
+        Weekday::Wed => "WE",
+        Weekday::Thu => "TH",
+        Weekday::Fri => "FR",
+        Weekday::Sat => "SA",
+        Weekday::Sun => "SU",
+    }
+}
+
+fn weekday_from_code(code: &str) -> Option<Weekday> {
+    [
+        Weekday::Mon,
+        Weekday::Tue,
+        Weekday::Wed,
+        Weekday::Thu,
+        Weekday::Fri,
+        Weekday::Sat,
+        Weekday::Sun,
+    ]
+    .into_iter()
+    .find(|day| weekday_code(*day) == code)
+}
+
+fn format_date_time(date_time: DateTime<Utc>) -> String {
+    format!("{}Z", date_time.format(DATE_TIME_FORMAT))
+}
+
+// Parse a UTC ("...Z") or floating date-time, or a plain date
+fn parse_date_time(value: &str) -> Option<DateTime<Utc>> {
+    let value = value.trim().trim_end_matches('Z');
+    if let Ok(date_time) = NaiveDateTime::parse_from_str(value, DATE_TIME_FORMAT) {
+        return Some(date_time.and_utc());
+    }
+    let date = NaiveDate::parse_from_str(value, DATE_FORMAT).ok()?;
+    Some(date.and_hms_opt(0, 0, 0)?.and_utc())
+}
+
+fn parse_date(value: &str) -> Option<NaiveDate> {
+    let date = value.trim().get(..8)?;
+    NaiveDate::parse_from_str(date, DATE_FORMAT).ok()
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+fn unescape(text: &str) -> String {
+    let mut out = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n' | 'N') => out.push('\n'),
+            Some(other) => out.push(other),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+// Split a comma separated list, respecting escaped commas
+fn split_list(value: &str) -> Vec<String> {
+    let mut items = Vec::new();
+    let mut start = 0;
+    let mut escaped = false;
+    for (i, c) in value.char_indices() {
+        match c {
+            '\\' if !escaped => {
+                escaped = true;
+                continue;
+            }
+            ',' if !escaped => {
+                items.push(unescape(&value[start..i]));
+                start = i + 1;
+            }
+            _ => {}
+        }
+        escaped = false;
+    }
+    items.push(unescape(&value[start..]));
+    items
+}
+
+// Lines longer than 75 octets are folded onto continuation lines starting
+// with a space, as the format requires
+fn fold(line: &str) -> String {
+    let mut out = String::new();
+    let mut length = 0;
+    for c in line.chars() {
+        if length + c.len_utf8() > 75 {
+            out.push_str("\r\n ");
+            length = 1;
+        }
+        out.push(c);
+        length += c.len_utf8();
+    }
+    out.push_str("\r\n");
+    out
+}
+
+fn unfold(contents: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in contents.lines() {
+        let line = line.trim_end_matches('\r');
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(continuation), Some(last)) => last.push_str(continuation),
+            _ => lines.push(line.to_string()),
+        }
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recurrences_too_long_to_count_are_left_out() {
+        assert_eq!(
+            parse_rrule("FREQ=WEEKLY;INTERVAL=3"),
+            Some(Recurrence::Days { days: 21 })
+        );
+        assert_eq!(parse_rrule("FREQ=WEEKLY;INTERVAL=1000000000"), None);
+        let todos = import(
+            "BEGIN:VCALENDAR\nBEGIN:VTODO\nSUMMARY:Pay rent\n\
+             RRULE:FREQ=WEEKLY;INTERVAL=1000000000\nEND:VTODO\nEND:VCALENDAR\n",
+        );
+        assert_eq!(todos[0].name, "Pay rent");
+        assert_eq!(todos[0].recurrence, None);
+    }
+}
@@ -0,0 +1,261 @@
+// src/keymap.rs
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+// What a key does. Keys are looked up in the bindings of the mode the app is
+// in, and the help popup lists the same bindings, so it can't go out of date.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Command {
+    Quit,
+    Suspend,
+    Help,
+    OpenCommandLine,
+    Up,
+    Down,
+    OpenDetails,
+    EditExternally,
+    EditDue,
+    EditRecurrence,
+    EditPriority,
+    EditTags,
+    EditBlockers,
+    EditNotes,
+    Close,
+    Confirm, // Finish what is being typed, or go ahead with what is shown
+    Cancel,
+    KeepMine,
+    TakeTheirs,
+    KeepAllMine,
+    TakeAllTheirs,
+}
+
+pub struct Binding {
+    pub keys: &'static [(KeyCode, KeyModifiers)],
+    pub command: Command,
+    pub help: &'static str,
+}
+
+// A key pressed without Ctrl
+const fn key(code: KeyCode) -> (KeyCode, KeyModifiers) {
+    (code, KeyModifiers::NONE)
+}
+
+// A character key pressed without Ctrl
+const fn chr(c: char) -> (KeyCode, KeyModifiers) {
+    key(KeyCode::Char(c))
+}
+
+const fn binding(
+    keys: &'static [(KeyCode, KeyModifiers)],
+    command: Command,
+    help: &'static str,
+) -> Binding {
+    Binding {
+        keys,
+        command,
+        help,
+    }
+}
+
+// Keys that work whatever the app is doing
+pub const GLOBAL: &[Binding] = &[binding(
+    &[(KeyCode::Char('z'), KeyModifiers::CONTROL)],
+    Command::Suspend,
+    "Suspend to the shell",
+)];
+
+pub const LIST: &[Binding] = &[
+    binding(&[chr('q')], Command::Quit, "Quit"),
+    binding(&[chr('?')], Command::Help, "Show this help"),
+    binding(&[chr(':')], Command::OpenCommandLine, "Type a command"),
+    binding(
+        &[chr('k'), key(KeyCode::Up)],
+        Command::Up,
+        "Select the todo above",
+    ),
+    binding(
+        &[chr('j'), key(KeyCode::Down)],
+        Command::Down,
+        "Select the todo below",
+    ),
+    binding(
+        &[key(KeyCode::Enter)],
+        Command::OpenDetails,
+        "Open the selected todo's details",
+    ),
+    binding(
+        &[chr('E')],
+        Command::EditExternally,
+        "Edit the selected todo in $EDITOR",
+    ),
+];
+
+pub const DETAILS: &[Binding] = &[
+    binding(&[chr('d')], Command::EditDue, "Set the due date"),
+    binding(&[chr('r')], Command::EditRecurrence, "Set how it repeats"),
+    binding(&[chr('p')], Command::EditPriority, "Set the priority"),
+    binding(&[chr('t')], Command::EditTags, "Set the tags"),
+    binding(
+        &[chr('b')],
+        Command::EditBlockers,
+        "Set the todos blocking it",
+    ),
+    binding(&[chr('e')], Command::EditNotes, "Edit the notes"),
+    binding(
+        &[chr('E')],
+        Command::EditExternally,
+        "Edit the name and notes in $EDITOR",
+    ),
+    binding(
+        &[key(KeyCode::Esc), key(KeyCode::Enter)],
+        Command::Close,
+        "Close the details",
+    ),
+    binding(&[chr('?')], Command::Help, "Show this help"),
+    binding(&[chr('q')], Command::Quit, "Quit"),
+];
+
+// While a todo's name is typed; other keys are typed into it
+pub const EDIT_NAME: &[Binding] = &[binding(
+    &[key(KeyCode::Enter), key(KeyCode::Esc)],
+    Command::Confirm,
+    "Save the name",
+)];
+
+// While a field of the details popup is typed
+pub const EDIT_FIELD: &[Binding] = &[
+    binding(&[key(KeyCode::Enter)], Command::Confirm, "Set the field"),
+    binding(
+        &[key(KeyCode::Esc)],
+        Command::Cancel,
+        "Leave the field as it was",
+    ),
+];
+
+// While the notes are edited in the details popup
+pub const EDIT_NOTES: &[Binding] = &[binding(
+    &[key(KeyCode::Esc)],
+    Command::Confirm,
+    "Save the notes",
+)];
+
+// While a command is typed after ':'
+pub const COMMAND: &[Binding] = &[
+    binding(&[key(KeyCode::Enter)], Command::Confirm, "Run the command"),
+    binding(&[key(KeyCode::Esc)], Command::Cancel, "Forget the command"),
+];
+
+pub const IMPORT_PREVIEW: &[Binding] = &[
+    binding(&[chr('k'), key(KeyCode::Up)], Command::Up, "Scroll up"),
+    binding(
+        &[chr('j'), key(KeyCode::Down)],
+        Command::Down,
+        "Scroll down",
+    ),
+    binding(
+        &[key(KeyCode::Enter), chr('y')],
+        Command::Confirm,
+        "Merge the todos",
+    ),
+    binding(
+        &[key(KeyCode::Esc), chr('n'), chr('q')],
+        Command::Cancel,
+        "Cancel the import",
+    ),
+];
+
+pub const CONFLICT: &[Binding] = &[
+    binding(&[chr('m')], Command::KeepMine, "Keep this version"),
+    binding(&[chr('t')], Command::TakeTheirs, "Take the file's version"),
+    binding(
+        &[chr('M')],
+        Command::KeepAllMine,
+        "Keep this version of all conflicts",
+    ),
+    binding(
+        &[chr('T')],
+        Command::TakeAllTheirs,
+        "Take the file's version of all conflicts",
+    ),
+];
+
+pub const HELP: &[Binding] = &[
+    binding(&[chr('k'), key(KeyCode::Up)], Command::Up, "Scroll up"),
+    binding(
+        &[chr('j'), key(KeyCode::Down)],
+        Command::Down,
+        "Scroll down",
+    ),
+    binding(
+        &[key(KeyCode::Esc), chr('?'), chr('q')],
+        Command::Close,
+        "Close the help",
+    ),
+];
+
+// What a read-only view allows, as it can only be looked at
+pub const READ_ONLY: &[Command] = &[
+    Command::Quit,
+    Command::Help,
+    Command::Up,
+    Command::Down,
+    Command::OpenDetails,
+    Command::Close,
+];
+
+// What the mouse does on the list, for the help popup
+pub const MOUSE: &[(&str, &str)] = &[
+    ("Click a checkbox", "Mark the todo done or not done"),
+    ("Click a title", "Rename the todo"),
+    ("Click or drag a progress bar", "Set the progress"),
+    ("Right-click a todo", "Open its details"),
+    ("Click [ + ]", "Add a todo"),
+    ("Click with a popup open", "Close the popup"),
+];
+
+// Function to find what a key does in the given bindings. Shift is part of
+// the character, so only Ctrl has to match.
+pub fn lookup(bindings: &[Binding], key_event: KeyEvent) -> Option<Command> {
+    let control = key_event.modifiers.contains(KeyModifiers::CONTROL);
+    bindings
+        .iter()
+        .find(|binding| {
+            binding.keys.iter().any(|(code, modifiers)| {
+                *code == key_event.code && modifiers.contains(KeyModifiers::CONTROL) == control
+            })
+        })
+        .map(|binding| binding.command)
+}
+
+// Function to name the keys of a binding the way the help shows them
+pub fn describe(binding: &Binding) -> String {
+    let names: Vec<String> = binding.keys.iter().map(key_name).collect();
+    names.join(" ")
+}
+
+// Function to name the first key bound to a command, for the hints shown
+// next to what it acts on
+pub fn hint(bindings: &[Binding], command: Command) -> String {
+    bindings
+        .iter()
+        .find(|binding| binding.command == command)
+        .and_then(|binding| binding.keys.first())
+        .map(key_name)
+        .unwrap_or_default()
+}
+
+fn key_name((code, modifiers): &(KeyCode, KeyModifiers)) -> String {
+    let name = match code {
+        KeyCode::Char(c) => c.to_string(),
+        KeyCode::Up => String::from("\u{2191}"),
+        KeyCode::Down => String::from("\u{2193}"),
+        KeyCode::Enter => String::from("Enter"),
+        KeyCode::Esc => String::from("Esc"),
+        code => format!("{:?}", code),
+    };
+    if modifiers.contains(KeyModifiers::CONTROL) {
+        format!("Ctrl+{}", name)
+    } else {
+        name
+    }
+}
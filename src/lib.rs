@@ -0,0 +1,404 @@
+// src/lib.rs
+
+//! The todo model behind the ratodui TUI, for tools that work with the same
+//! todos: the [`Todo`] type, operations on a list of todos, reading and
+//! saving them in the configured [`storage`], and the progress bar the TUI
+//! draws for them.
+//!
+//! ```no_run
+//! use ratodui::{push_todo, read_todos, save_todos, Todo};
+//!
+//! let mut todos = read_todos()?;
+//! push_todo(&mut todos, Todo::new("Water the plants"));
+//! save_todos(&todos)?;
+//! # Ok::<(), ratodui::storage::StorageError>(())
+//! ```
+
+pub mod config;
+pub mod crypto;
+mod csv;
+pub mod formats;
+pub mod git;
+mod ical;
+pub mod lock;
+mod markdown;
+pub mod merge;
+mod org;
+pub mod recurrence;
+pub mod schema;
+mod sqlite;
+pub mod storage;
+mod taskwarrior;
+mod todotxt;
+
+use chrono::{DateTime, Local, NaiveDate, Utc};
+use recurrence::Recurrence;
+use serde::{Deserialize, Serialize};
+use std::{env, fs, io, path::Path, time::SystemTime};
+use storage::StorageError;
+
+/// A todo as stored in todos.json
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct Todo {
+    /// Stable identifier, assigned when the todo is added or loaded
+    #[serde(default)]
+    pub id: u64,
+    /// What has to be done
+    pub name: String,
+    /// Progress in percentage (0 - 100)
+    pub progress: u16,
+    /// Whether the todo is done, see [`Todo::set_completed`]
+    #[serde(default)]
+    pub completed: bool,
+    /// Progress changes, one entry per drag gesture
+    #[serde(default)]
+    pub history: Vec<ProgressEntry>,
+    /// Free-form multi-line description
+    #[serde(default)]
+    pub notes: String,
+    /// Date the todo has to be done by
+    #[serde(default)]
+    pub due: Option<NaiveDate>,
+    /// Completing the todo spawns the next occurrence
+    #[serde(default)]
+    pub recurrence: Option<Recurrence>,
+    /// IDs of todos that have to be completed first
+    #[serde(default)]
+    pub blocked_by: Vec<u64>,
+    /// 'A' (highest) to 'Z', as in todo.txt
+    #[serde(default)]
+    pub priority: Option<char>,
+    /// Projects ("+work") and contexts ("@home")
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// When the todo was added, unknown for todos from older files
+    #[serde(default)]
+    pub created_at: Option<DateTime<Utc>>,
+    /// When the todo was last completed, None while it is open
+    #[serde(default)]
+    pub completed_at: Option<DateTime<Utc>>,
+}
+
+impl Todo {
+    /// A new open todo without progress. Its ID is given by [`push_todo`].
+    pub fn new(name: &str) -> Self {
+        Todo {
+            id: 0,
+            name: name.to_string(),
+            progress: 0,
+            completed: false, // Initialize as not completed
+            history: Vec::new(),
+            notes: String::new(),
+            due: None,
+            recurrence: None,
+            blocked_by: Vec::new(),
+            priority: None,
+            tags: Vec::new(),
+            created_at: Some(Utc::now()),
+            completed_at: None,
+        }
+    }
+
+    /// Complete or reopen the todo, keeping track of when it was completed
+    pub fn set_completed(&mut self, completed: bool) {
+        self.completed = completed;
+        self.completed_at = if completed { Some(Utc::now()) } else { None };
+    }
+}
+
+/// The progress of a todo at some point, see [`record_progress`]
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct ProgressEntry {
+    /// When the progress was set
+    pub at: DateTime<Utc>,
+    /// Progress in percentage (0 - 100)
+    pub progress: u16,
+}
+
+/// Add a todo to the list, giving it the next free ID
+pub fn push_todo(todos: &mut Vec<Todo>, mut todo: Todo) {
+    todo.id = next_id(todos);
+    todos.push(todo);
+}
+
+/// Find the position of the todo with the given ID
+pub fn index_of(todos: &[Todo], id: u64) -> Option<usize> {
+    todos.iter().position(|todo| todo.id == id)
+}
+
+/// Find the next unused todo ID. IDs that were stored before are never used
+/// again, even once their todo is removed or archived.
+pub fn next_id(todos: &[Todo]) -> u64 {
+    let highest = todos.iter().map(|todo| todo.id).max().unwrap_or(0);
+    (highest + 1).max(storage::next_free_id())
+}
+
+/// Check if any of a todo's blockers is still open
+pub fn is_blocked(todo: &Todo, todos: &[Todo]) -> bool {
+    todo.blocked_by.iter().any(|id| {
+        todos
+            .iter()
+            .any(|other| other.id == *id && !other.completed)
+    })
+}
+
+/// List the open todos that only wait on the given todo,
+/// i.e. the ones that become unblocked once it is completed
+pub fn unblocked_by(todos: &[Todo], id: u64) -> Vec<&Todo> {
+    todos
+        .iter()
+        .filter(|todo| !todo.completed && todo.blocked_by.contains(&id))
+        .filter(|todo| {
+            todo.blocked_by.iter().all(|blocker| {
+                *blocker == id
+                    || !todos
+                        .iter()
+                        .any(|other| other.id == *blocker && !other.completed)
+            })
+        })
+        .collect()
+}
+
+/// Check if `id` is transitively blocked by `target`
+pub fn depends_on(todos: &[Todo], id: u64, target: u64) -> bool {
+    let mut stack = vec![id];
+    let mut seen = Vec::new();
+    while let Some(current) = stack.pop() {
+        if current == target {
+            return true;
+        }
+        if seen.contains(&current) {
+            continue;
+        }
+        seen.push(current);
+        if let Some(todo) = todos.iter().find(|todo| todo.id == current) {
+            stack.extend(&todo.blocked_by);
+        }
+    }
+    false
+}
+
+/// Parse a list of blocker IDs for the todo at `index`, dropping
+/// unknown IDs and any that would make the todo depend on itself
+pub fn parse_blockers(input: &str, todos: &[Todo], index: usize) -> Vec<u64> {
+    let own_id = todos[index].id;
+    let mut blockers = Vec::new();
+    for part in input.split(|c: char| c == ',' || c.is_whitespace()) {
+        let Ok(id) = part.trim_start_matches('#').parse::<u64>() else {
+            continue;
+        };
+        let exists = todos.iter().any(|todo| todo.id == id);
+        if exists && !blockers.contains(&id) && !depends_on(todos, id, own_id) {
+            blockers.push(id);
+        }
+    }
+    blockers
+}
+
+/// Create the next occurrence of a completed recurring todo.
+/// The recurrence moves to the new todo so it is only spawned once; if there
+/// is no next date to give it, the recurrence is dropped.
+/// The priority and tags carry over; `blocked_by` doesn't, as the blockers
+/// were what this occurrence waited on and it is done.
+pub fn next_occurrence(todo: &mut Todo, completed_on: NaiveDate) -> Option<Todo> {
+    let recurrence = todo.recurrence.take()?;
+    let mut next = Todo::new(&todo.name);
+    next.notes = todo.notes.clone();
+    next.priority = todo.priority;
+    next.tags = todo.tags.clone();
+    next.due = Some(recurrence.next_due(todo.due, completed_on)?);
+    next.recurrence = Some(recurrence);
+    Some(next)
+}
+
+/// Append the current progress to the history if it changed
+pub fn record_progress(todo: &mut Todo) {
+    let last = todo.history.last().map(|entry| entry.progress);
+    if last != Some(todo.progress) {
+        todo.history.push(ProgressEntry {
+            at: Utc::now(),
+            progress: todo.progress,
+        });
+    }
+}
+
+/// Build the ASCII progress bar of a todo, `width` columns wide including
+/// the brackets and the percentage
+///
+/// ```
+/// assert_eq!(ratodui::build_progress_bar(40, 16), "[####------] 40%");
+/// ```
+pub fn build_progress_bar(progress: u16, width: usize) -> String {
+    // Width is the total width, we need to subtract for brackets and percentage
+    let percent_str = format!(" {}%", progress);
+    let extra_chars = 2 + percent_str.len(); // '[' and ']' and percentage
+
+    if width <= extra_chars {
+        // Not enough space to render progress bar
+        return format!("{}%", percent_str);
+    }
+
+    let bar_width = width - extra_chars;
+
+    let filled_blocks = (progress as usize * bar_width) / 100;
+    let empty_blocks = bar_width - filled_blocks;
+    format!(
+        "[{}{}]{}",
+        "#".repeat(filled_blocks),
+        "-".repeat(empty_blocks),
+        percent_str
+    )
+}
+
+/// Read todos from the configured storage as they are stored
+pub fn read_todos() -> Result<Vec<Todo>, StorageError> {
+    // A file that can't be parsed is reported rather than read as empty,
+    // so the todos in it aren't overwritten by the next save
+    let mut todos = storage::configured()?.read()?;
+
+    // Give todos from older files an ID
+    for i in 0..todos.len() {
+        if todos[i].id == 0 {
+            todos[i].id = next_id(&todos);
+        }
+    }
+
+    Ok(todos)
+}
+
+/// Read the archived todos, optionally only those whose name
+/// contains `pattern`
+pub fn read_archive(pattern: Option<&str>) -> Result<Vec<Todo>, StorageError> {
+    storage::configured()?.read_archive(pattern)
+}
+
+/// Load todos to edit them, applying the startup cleanup: completed todos
+/// move to the archive and recurring ones are replaced by their next
+/// occurrence. [`read_todos`] reads them as they are stored.
+pub fn load_todos() -> Result<Vec<Todo>, StorageError> {
+    // Nobody else may save between reading the todos and archiving
+    let _lock = storage::lock_for_update();
+    let mut todos = read_todos()?;
+
+    // Recurring todos completed elsewhere are replaced by their next occurrence
+    let today = Local::now().date_naive();
+    let spawned: Vec<Todo> = todos
+        .iter_mut()
+        .filter(|todo| todo.completed)
+        .filter_map(|todo| next_occurrence(todo, today))
+        .collect();
+
+    // Move completed todos to the archive (on startup), and drop references
+    // to them so their IDs can't come back as blockers
+    let (completed, mut todos): (Vec<Todo>, Vec<Todo>) =
+        todos.into_iter().partition(|todo| todo.completed);
+    for todo in &mut todos {
+        todo.blocked_by
+            .retain(|id| !completed.iter().any(|done| done.id == *id));
+    }
+    for todo in spawned {
+        push_todo(&mut todos, todo);
+    }
+    // Failing to update the markdown file or git is left to the next save
+    if !completed.is_empty() {
+        match write_todos(&todos, &completed) {
+            Err(e) if !e.todos_saved() => return Err(e),
+            _ => {}
+        }
+    }
+
+    Ok(todos)
+}
+
+/// Save todos to the configured storage. See [`write_todos`] for what is
+/// returned.
+pub fn save_todos(todos: &[Todo]) -> Result<bool, StorageError> {
+    write_todos(todos, &[])
+}
+
+/// Write the list to the configured storage, adding `archived` to the archive.
+/// Returns `Ok(false)` if nothing was written because the todos were saved by
+/// another program since we read them; the TUI then merges its changes.
+/// An error for which [`StorageError::todos_saved`] holds comes after the
+/// todos were written.
+pub fn write_todos(todos: &[Todo], archived: &[Todo]) -> Result<bool, StorageError> {
+    let storage = storage::configured()?;
+    if !storage.write(todos, archived)? {
+        return Ok(false);
+    }
+    // Keep a bound markdown file in sync with the list
+    if let Some(markdown_path) = storage.markdown_binding() {
+        if let Err(e) = sync_markdown(&markdown_path, todos) {
+            return Err(StorageError::Markdown(markdown_path, e));
+        }
+    }
+    // In git mode every save is a commit
+    if git::is_enabled() {
+        git::commit_save(storage.path(), todos, archived).map_err(StorageError::Git)?;
+    }
+    Ok(true)
+}
+
+// Function to rewrite the checklist in a bound markdown file
+fn sync_markdown(markdown_path: &Path, todos: &[Todo]) -> io::Result<()> {
+    let contents = match fs::read_to_string(markdown_path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e),
+    };
+    let rewritten = markdown::rewrite(&contents, todos);
+    if rewritten != contents {
+        fs::write(markdown_path, rewritten)?;
+    }
+    Ok(())
+}
+
+/// Bind the list to a markdown file, or unbind it with None.
+/// The checklist is written on the next save. Binding a file whose checklist
+/// has other items than `todos` is refused unless `overwrite` is set, as they
+/// would be lost.
+pub fn bind_markdown(
+    markdown_path: Option<&Path>,
+    todos: &[Todo],
+    overwrite: bool,
+) -> Result<(), String> {
+    let storage = storage::configured().map_err(|e| e.to_string())?;
+
+    // Store an absolute path so the binding works from any directory
+    let markdown_path = match markdown_path {
+        Some(path) if path.is_relative() => {
+            Some(env::current_dir().map_err(|e| e.to_string())?.join(path))
+        }
+        path => path.map(Path::to_path_buf),
+    };
+
+    if let (Some(path), false) = (&markdown_path, overwrite) {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e.to_string()),
+        };
+        if markdown::checklist_differs(&contents, todos) {
+            return Err(format!(
+                "{} has checklist items that aren't todos; import them first, \
+                 or bind with --overwrite to replace them",
+                path.display()
+            ));
+        }
+    }
+
+    match storage.set_markdown_binding(markdown_path.as_deref()) {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(String::from(
+            "the todos were just saved by another program, please try again",
+        )),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Something that changes whenever the stored todos do, like the
+/// modification time and size of the data file, to tell whether they
+/// changed without reading them
+pub fn data_file_stamp() -> Option<(SystemTime, u64)> {
+    storage::configured().ok()?.stamp()
+}
@@ -0,0 +1,90 @@
+// src/lock.rs
+
+//! Locks that keep two programs from writing the todos at the same time.
+
+use std::{
+    fs::{File, OpenOptions, TryLockError},
+    io,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+// The update lock file while this process holds it, and how many guards
+// are out. Nested guards share the lock, as locking the same file twice
+// from one process would wait forever.
+static HELD: Mutex<Option<(File, usize)>> = Mutex::new(None);
+
+/// Advisory lock around a read-modify-write of the data file. Other
+/// ratodui processes wait for it to be released when the guard is dropped.
+pub struct UpdateLock;
+
+impl Drop for UpdateLock {
+    fn drop(&mut self) {
+        let mut held = HELD.lock().unwrap();
+        if let Some((_, count)) = held.as_mut() {
+            *count -= 1;
+            if *count == 0 {
+                *held = None; // Closing the file releases the lock
+            }
+        }
+    }
+}
+
+/// Wait for the update lock next to the data file
+pub fn lock_for_update(data_file: &Path) -> io::Result<UpdateLock> {
+    let mut held = HELD.lock().unwrap();
+    match held.as_mut() {
+        Some((_, count)) => *count += 1,
+        None => {
+            let file = open(&lock_path(data_file, "lock"))?;
+            file.lock()?;
+            *held = Some((file, 1));
+        }
+    }
+    Ok(UpdateLock)
+}
+
+/// Take the lock a running TUI holds for as long as it is open, or return
+/// None if another instance already has it
+pub fn try_lock_instance(data_file: &Path) -> io::Result<Option<File>> {
+    let file = open(&lock_path(data_file, "tui.lock"))?;
+    match file.try_lock() {
+        Ok(()) => Ok(Some(file)),
+        Err(TryLockError::WouldBlock) => Ok(None),
+        Err(TryLockError::Error(e)) => Err(e),
+    }
+}
+
+fn open(path: &Path) -> io::Result<File> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(path)
+}
+
+// Lock files sit next to the data file, e.g. todos.lock
+fn lock_path(data_file: &Path, extension: &str) -> PathBuf {
+    data_file.with_extension(extension)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs};
+
+    #[test]
+    fn only_one_instance_gets_the_lock() {
+        let data_file = env::temp_dir().join(format!("ratodui-lock-{}.json", std::process::id()));
+        let first = try_lock_instance(&data_file).unwrap();
+        assert!(first.is_some());
+        assert!(try_lock_instance(&data_file).unwrap().is_none());
+
+        drop(first);
+        assert!(try_lock_instance(&data_file).unwrap().is_some());
+        let _ = fs::remove_file(lock_path(&data_file, "tui.lock"));
+    }
+}
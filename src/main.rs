@@ -1,562 +1,733 @@
 // src/main.rs
 
+mod app;
+mod cli;
+mod editor;
+mod keymap;
+
+use app::{Action, App, Effect, ImportPreview, Reload};
+use chacha20poly1305::aead::{rand_core::RngCore, OsRng};
 use crossterm::{
+    cursor,
     event::{
-        self, DisableMouseCapture, EnableMouseCapture, Event as CEvent, KeyCode, MouseButton,
-        MouseEventKind,
+        self, DisableFocusChange, DisableMouseCapture, EnableFocusChange, EnableMouseCapture,
+        Event as CEvent, KeyCode, KeyModifiers, MouseEventKind,
     },
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
-use directories::ProjectDirs;
 use ratatui::{
     backend::{Backend, CrosstermBackend},
-    layout::{Constraint, Direction, Layout, Rect},
     style::{Color, Style},
-    text::Span,
-    widgets::{Paragraph, Wrap},
+    text::{Span, Spans},
+    widgets::{Block, Borders, Paragraph},
     Terminal,
 };
-use serde::{Deserialize, Serialize};
+use ratodui::{
+    bind_markdown, crypto, crypto::CryptoError, data_file_stamp, formats, index_of, load_todos,
+    lock, merge, push_todo, read_archive, read_todos, save_todos, storage, Todo,
+};
+use signal_hook::{
+    consts::{SIGCONT, SIGHUP, SIGTERM, SIGTSTP},
+    iterator::Signals,
+    low_level,
+};
 use std::{
+    any::Any,
+    env,
     error::Error,
-    fs,
-    io,
-    sync::mpsc,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    os::{raw::c_int, unix::fs::OpenOptionsExt},
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    process::Command,
+    sync::{mpsc, Arc, Condvar, Mutex, MutexGuard},
     thread,
     time::{Duration, Instant},
 };
 
-#[derive(Serialize, Deserialize)]
-struct Todo {
-    name: String,
-    progress: u16, // Progress in percentage (0 - 100)
-    #[serde(default)]
-    completed: bool, // Field to mark completion
-}
+// How often the data file is checked for changes by other programs, and
+// settled changes are written
+const CHECK_INTERVAL: Duration = Duration::from_millis(250);
+
+// How long the input thread waits for an event before looking whether it
+// should stop reading the terminal
+const INPUT_WAIT: Duration = Duration::from_millis(100);
 
 enum Event<I> {
     Input(I),
-    Tick,
+    Signal(c_int),
+    Panic(Box<dyn Any + Send>), // The input thread panicked
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum InputState {
+    Reading,
+    Paused,  // Another program has the terminal
+    Stopped, // The app is exiting
+}
+
+// Lets the main thread stop the input thread from reading the terminal,
+// for a while or for good
+struct InputControl {
+    state: Mutex<InputState>,
+    changed: Condvar,
+    reading: Mutex<()>, // Held by the input thread while it waits for an event
+}
+
+impl InputControl {
+    fn new() -> Self {
+        InputControl {
+            state: Mutex::new(InputState::Reading),
+            changed: Condvar::new(),
+            reading: Mutex::new(()),
+        }
+    }
+
+    // Function to stop reading until `resume` is called. Returns once the
+    // input thread is done waiting for its current event.
+    fn pause(&self) -> MutexGuard<'_, ()> {
+        self.set(InputState::Paused);
+        self.reading.lock().unwrap()
+    }
+
+    fn resume(&self) {
+        self.set(InputState::Reading);
+    }
+
+    fn stop(&self) {
+        self.set(InputState::Stopped);
+    }
+
+    fn set(&self, state: InputState) {
+        *self.state.lock().unwrap() = state;
+        self.changed.notify_all();
+    }
+
+    // Function for the input thread to sleep while input is paused. Returns
+    // false once it should stop for good.
+    fn wait_while_paused(&self) -> bool {
+        let state = self.state.lock().unwrap();
+        let state = self
+            .changed
+            .wait_while(state, |state| *state == InputState::Paused)
+            .unwrap();
+        *state == InputState::Reading
+    }
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
+    // Run a non-interactive command if one was given
+    let args: Vec<String> = env::args().skip(1).collect();
+    if !args.is_empty() {
+        std::process::exit(cli::run(&args));
+    }
+
+    // Only one TUI edits the todos at a time; others get a read-only view
+    let (mut instance_lock, read_only) =
+        match storage::configured().map(|storage| lock::try_lock_instance(storage.path())) {
+            Ok(Ok(Some(instance_lock))) => (Some(instance_lock), false),
+            Ok(Ok(None)) => (None, true),
+            _ => (None, false), // Locking isn't supported here, so go ahead without
+        };
+
+    // Encrypted todos are unlocked before they are loaded, with the passphrase
+    // from the environment or else by asking for it
+    if let Some(contents) = storage::locked_contents() {
+        let unlocked = env::var(crypto::PASSPHRASE_VAR)
+            .is_ok_and(|passphrase| crypto::unlock(&contents, &passphrase).is_ok());
+        if !unlocked && !unlock_screen(&contents)? {
+            return Ok(());
+        }
+    }
+
+    // Initialize todos before taking over the terminal, so errors stay visible.
+    // A read-only view shows the file as it is.
+    let loaded = if read_only {
+        read_todos()
+    } else {
+        load_todos()
+    };
+    let todos = match loaded {
+        Ok(todos) => todos,
+        Err(e) => {
+            eprintln!("ratodui: {}", e);
+            std::process::exit(cli::EXIT_FAILURE);
+        }
+    };
+
     // Setup terminal
     enable_raw_mode()?;
     let mut stdout = io::stdout();
-    execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
+    execute!(
+        stdout,
+        EnterAlternateScreen,
+        EnableMouseCapture,
+        EnableFocusChange
+    )?;
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
 
+    // Give the terminal back before a panic message is printed, so it can be read
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        let _ = restore_terminal();
+        default_hook(info);
+    }));
+
     // Channel to receive input events
     let (tx, rx) = mpsc::channel();
-    let tick_rate = Duration::from_millis(250);
-    let tx_clone = tx.clone();
-    thread::spawn(move || {
-        let mut last_tick = Instant::now();
-        loop {
-            // Poll for event
-            let timeout = tick_rate
-                .checked_sub(last_tick.elapsed())
-                .unwrap_or_else(|| Duration::from_secs(0));
-            if event::poll(timeout).unwrap() {
-                match event::read().unwrap() {
-                    CEvent::Mouse(mouse_event) => {
-                        tx_clone
-                            .send(Event::Input(CEvent::Mouse(mouse_event)))
-                            .unwrap();
-                    }
-                    CEvent::Key(key_event) => {
-                        tx_clone
-                            .send(Event::Input(CEvent::Key(key_event)))
-                            .unwrap();
-                        // Removed the break condition here
-                    }
-                    _ => {}
-                }
+    let input = Arc::new(InputControl::new());
+    let input_thread = {
+        let (tx, input) = (tx.clone(), input.clone());
+        thread::spawn(move || {
+            // A panic here ends the app like one in the main loop does
+            let reading = panic::catch_unwind(AssertUnwindSafe(|| read_input(&tx, &input)));
+            if let Err(payload) = reading {
+                let _ = tx.send(Event::Panic(payload));
             }
-            if last_tick.elapsed() >= tick_rate {
-                tx_clone.send(Event::Tick).unwrap();
-                last_tick = Instant::now();
+        })
+    };
+
+    // Signals are handled between events: SIGTERM and SIGHUP end the app
+    // like 'q' does, SIGTSTP suspends it like Ctrl+Z
+    let mut signals = Signals::new([SIGTERM, SIGHUP, SIGTSTP, SIGCONT])?;
+    let signals_handle = signals.handle();
+    let signal_thread = thread::spawn(move || {
+        for signal in signals.forever() {
+            if tx.send(Event::Signal(signal)).is_err() {
+                break;
             }
         }
     });
 
-    // Initialize todos
-    let mut todos = load_todos();
+    // The app remembers the todos as loaded, to merge changes other programs make to them
+    let mut app = App::new(todos, terminal.size()?, read_only);
+    app.file_stamp = data_file_stamp();
+    if let Ok(storage) = storage::configured() {
+        let file_name = storage.path().file_name().unwrap_or_default();
+        app.file_name = file_name.to_string_lossy().into_owned();
+    }
 
     // If no todos were loaded, initialize with a new todo
-    if todos.is_empty() {
-        todos.push(Todo {
-            name: String::from("New Todo"),
-            progress: 0,
-            completed: false, // Initialize as not completed
-        });
+    if app.todos.is_empty() && !read_only {
+        push_todo(&mut app.todos, Todo::new("New Todo"));
+    }
+
+    // A panic still saves the todos on the way out
+    let ended = panic::catch_unwind(AssertUnwindSafe(|| {
+        event_loop(&mut terminal, &mut app, &rx, &input, &mut instance_lock)
+    }));
+
+    // Stop reading the terminal before handing it back
+    input.stop();
+    signals_handle.close();
+    let _ = input_thread.join();
+    let _ = signal_thread.join();
+
+    // Before exiting, take in the changes other programs made since the last
+    // check, keeping our version of todos changed on both sides, and save
+    let mut saved = Ok(true);
+    if !app.read_only {
+        app.finish_pending_edits();
+        if app.reload.is_none() && data_file_stamp() != app.file_stamp {
+            app.reload = read_external_changes(&app.base, &app.todos).unwrap_or(None);
+        }
+        if let Some(mut changes) = app.reload.take() {
+            changes.merged.conflicts.clear(); // Our versions are already in the list
+            app.finish_reload(changes);
+        }
+        saved = save_todos(&app.todos);
     }
 
-    // Variables for mouse interaction
-    let mut dragging = false;
-    let mut drag_index = None;
+    // Cleanup before exiting, then go on with how the app ended
+    let restored = restore_terminal();
+    match saved {
+        Ok(true) => {}
+        Ok(false) => eprintln!("Failed to save todos: another program saved them meanwhile"),
+        Err(e) if e.todos_saved() => eprintln!("Saved the todos, but {}", e),
+        Err(e) => eprintln!("Failed to save todos: {}", e),
+    }
+    match ended {
+        Ok(Ok(None)) => {}
+        Ok(Ok(Some(signal))) => low_level::emulate_default_handler(signal)?,
+        Ok(Err(e)) => return Err(e),
+        Err(payload) => panic::resume_unwind(payload),
+    }
+    restored?;
+    terminal.show_cursor()?;
 
-    // Variables for editing todo names
-    let mut editing_index: Option<usize> = None;
-    let mut input_buffer = String::new();
-    let mut just_started_editing = false; // Flag to indicate if we just entered edit mode
+    Ok(())
+}
 
-    // Main loop
+// Function to run the app until it quits. Returns the signal that ended it, if any.
+fn event_loop<B: Backend + io::Write>(
+    terminal: &mut Terminal<B>,
+    app: &mut App,
+    rx: &mpsc::Receiver<Event<CEvent>>,
+    input: &InputControl,
+    instance_lock: &mut Option<File>,
+) -> Result<Option<c_int>, Box<dyn Error>> {
+    let mut redraw = true;
+    let mut last_check = Instant::now();
     loop {
-        // Get the terminal size
-        let size = terminal.size()?;
-        // Compute the layout chunks
-        let chunks = compute_chunks(size, &todos);
+        // Timers run even while events keep coming in
+        if last_check.elapsed() >= CHECK_INTERVAL {
+            last_check = Instant::now();
+            redraw |= check_timers(app, instance_lock);
+        }
 
-        // Rendering
-        terminal.draw(|f| {
-            ui(f, &todos, editing_index, &input_buffer);
-        })?;
+        // Rendering, only when something may look different
+        if redraw {
+            app.area = terminal.size()?;
+            terminal.draw(|f| app.view(f))?;
+            redraw = false;
+        }
 
-        // Event handling
-        match rx.recv()? {
+        // Event handling, waking up for the next check if nothing happens before
+        let timeout = CHECK_INTERVAL.saturating_sub(last_check.elapsed());
+        let event = match rx.recv_timeout(timeout) {
+            Ok(event) => event,
+            Err(mpsc::RecvTimeoutError::Timeout) => continue,
+            Err(e) => return Err(e.into()),
+        };
+        match event {
             Event::Input(event) => {
-                if let Some(i) = editing_index {
-                    // We are in edit mode
-                    match event {
-                        CEvent::Key(key_event) => {
-                            match key_event.code {
-                                KeyCode::Char(c) => {
-                                    input_buffer.push(c);
-                                }
-                                KeyCode::Backspace => {
-                                    input_buffer.pop();
-                                }
-                                KeyCode::Enter | KeyCode::Esc => {
-                                    // Update the todo's name and exit edit mode
-                                    todos[i].name = input_buffer.clone();
-                                    input_buffer.clear();
-                                    editing_index = None;
-                                    // Save the todos after renaming
-                                    save_todos(&todos);
-                                }
-                                _ => {
-                                    // Any other key press exits edit mode and saves the name
-                                    todos[i].name = input_buffer.clone();
-                                    input_buffer.clear();
-                                    editing_index = None;
-                                    // Save the todos after renaming
-                                    save_todos(&todos);
-
-                                    // Now handle the key event as usual
-                                    if key_event.code == KeyCode::Char('q') {
-                                        break; // Exit the main loop
-                                    }
-                                    // Handle other key events if needed
-                                }
-                            }
-                        }
-                        CEvent::Mouse(mouse_event) => {
-                            if just_started_editing {
-                                // Ignore the mouse event that initiated edit mode
-                                just_started_editing = false;
-                            } else {
-                                match mouse_event.kind {
-                                    MouseEventKind::Moved => {
-                                        // Do nothing, stay in edit mode
-                                    }
-                                    _ => {
-                                        // For other mouse events, exit edit mode
-                                        todos[i].name = input_buffer.clone();
-                                        input_buffer.clear();
-                                        editing_index = None;
-                                        // Save the todos after renaming
-                                        save_todos(&todos);
-
-                                        // Now process the mouse event
-                                        process_mouse_event(
-                                            mouse_event,
-                                            &mut todos,
-                                            &mut dragging,
-                                            &mut drag_index,
-                                            &chunks,
-                                            &mut editing_index,
-                                            &mut input_buffer,
-                                            &mut just_started_editing,
-                                        );
-                                    }
-                                }
-                            }
-                        }
-                        _ => {
-                            // Any other event exits edit mode and saves the name
-                            todos[i].name = input_buffer.clone();
-                            input_buffer.clear();
-                            editing_index = None;
-                            // Save the todos after renaming
-                            save_todos(&todos);
-                        }
+                let action = match event {
+                    CEvent::Key(key_event) => Action::Key(key_event),
+                    // Moving the mouse without a button pressed changes nothing
+                    CEvent::Mouse(mouse_event) if mouse_event.kind == MouseEventKind::Moved => {
+                        continue;
+                    }
+                    CEvent::Mouse(mouse_event) => Action::Mouse(mouse_event),
+                    CEvent::Resize(width, height) => Action::Resize(width, height),
+                    // Write unsaved changes when the user switches away from us
+                    CEvent::FocusLost => {
+                        flush_todos(app);
+                        redraw = true;
+                        continue;
+                    }
+                    // Take in changes made while the user was elsewhere
+                    CEvent::FocusGained => {
+                        redraw = check_for_changes(app);
+                        continue;
                     }
-                } else {
-                    // Not in edit mode
-                    match event {
-                        CEvent::Key(key_event) => {
-                            if key_event.code == KeyCode::Char('q') {
-                                break; // Exit the main loop
+                    _ => continue,
+                };
+                redraw = true;
+                // Messages are shown until the next key press
+                if let Action::Key(_) = action {
+                    app.status_message = None;
+                }
+                // Take in changes other programs made before acting on the event
+                check_for_changes(app);
+
+                match app.update(action) {
+                    Some(Effect::Quit) => return Ok(None),
+                    Some(Effect::Suspend) => suspend(terminal, app, input)?,
+                    Some(Effect::EditExternally(id)) => {
+                        flush_todos(app);
+                        let _guard = input.pause();
+                        if let Some(i) = index_of(&app.todos, id) {
+                            if let Err(e) = edit_externally(terminal, &mut app.todos[i]) {
+                                app.status_message = Some(format!("Failed to run editor: {}", e));
                             }
-                            // Handle other key events if needed
-                        }
-                        CEvent::Mouse(mouse_event) => {
-                            process_mouse_event(
-                                mouse_event,
-                                &mut todos,
-                                &mut dragging,
-                                &mut drag_index,
-                                &chunks,
-                                &mut editing_index,
-                                &mut input_buffer,
-                                &mut just_started_editing,
-                            );
                         }
-                        _ => {}
+                        input.resume();
+                        app.changed();
                     }
+                    Some(Effect::RunCommand(command)) => {
+                        let result = run_command(&command, &app.todos, &mut app.import_preview);
+                        app.status_message = Some(result.unwrap_or_else(|e| e));
+                        app.changed();
+                    }
+                    None => {}
+                }
+                if app.needs_save() {
+                    flush_todos(app);
                 }
             }
-            Event::Tick => {}
+            Event::Signal(SIGTSTP) => {
+                suspend(terminal, app, input)?;
+                redraw = true;
+            }
+            // Whoever stopped us may have reset the terminal in the meantime
+            Event::Signal(SIGCONT) => {
+                resume_terminal(terminal)?;
+                redraw = true;
+            }
+            Event::Signal(signal) => return Ok(Some(signal)),
+            Event::Panic(payload) => panic::resume_unwind(payload),
         }
     }
-
-    // Before exiting, save the todos
-    save_todos(&todos);
-
-    // Cleanup before exiting
-    disable_raw_mode()?;
-    execute!(
-        terminal.backend_mut(),
-        LeaveAlternateScreen,
-        DisableMouseCapture
-    )?;
-    terminal.show_cursor()?;
-
-    Ok(())
 }
 
-// Function to render the UI
-fn ui<B: Backend>(
-    f: &mut ratatui::Frame<B>,
-    todos: &[Todo],
-    editing_index: Option<usize>,
-    input_buffer: &str,
-) {
-    let chunks = compute_chunks(f.size(), todos);
-
-    for (i, todo) in todos.iter().enumerate() {
-        let mut style = Style::default();
-        let title: String;
-        let checkbox = if todo.completed { "[x]" } else { "[ ]" };
-
-        if editing_index == Some(i) {
-            // Render input buffer with a cursor
-            title = format!("{}_", input_buffer); // Add cursor
-            style = Style::default().fg(Color::Yellow);
-        } else {
-            title = todo.name.clone();
+// Function to do what has to happen every CHECK_INTERVAL. Returns whether the
+// screen needs to be redrawn.
+fn check_timers(app: &mut App, instance_lock: &mut Option<File>) -> bool {
+    let mut changed = false;
+    // Start editing once the other instance has exited
+    if app.read_only {
+        if let Ok(Ok(Some(lock))) =
+            storage::configured().map(|storage| lock::try_lock_instance(storage.path()))
+        {
+            *instance_lock = Some(lock);
+            app.read_only = false;
+            app.status_message = Some(String::from(
+                "The other ratodui has exited; the todos can be edited now",
+            ));
+            changed = true;
         }
-
-        let area = chunks[i];
-
-        // Inside each chunk (line), create a horizontal layout
-        let horizontal_chunks = Layout::default()
-            .direction(Direction::Horizontal)
-            .constraints(
-                [
-                    Constraint::Length(35), // Adjusted length for checkbox and title
-                    Constraint::Min(1),     // Remaining space for progress bar
-                ]
-                .as_ref(),
-            )
-            .split(area);
-
-        // Further split the first chunk into checkbox and title
-        let checkbox_and_title_chunks = Layout::default()
-            .direction(Direction::Horizontal)
-            .constraints(
-                [
-                    Constraint::Length(4), // For '[ ] '
-                    Constraint::Min(1),    // Remaining space for title
-                ]
-                .as_ref(),
-            )
-            .split(horizontal_chunks[0]);
-
-        // Render the checkbox
-        let checkbox_paragraph = Paragraph::new(Span::raw(checkbox));
-        f.render_widget(checkbox_paragraph, checkbox_and_title_chunks[0]);
-
-        // Render the title
-        let title_paragraph = Paragraph::new(Span::styled(title, style));
-        f.render_widget(title_paragraph, checkbox_and_title_chunks[1]);
-
-        // Now build and render the progress bar in the second chunk
-
-        let progress_bar_width = horizontal_chunks[1].width;
-
-        let progress_bar = build_progress_bar(todo.progress, progress_bar_width as usize);
-
-        let progress_bar_paragraph = Paragraph::new(Span::raw(progress_bar));
-
-        f.render_widget(progress_bar_paragraph, horizontal_chunks[1]);
     }
-
-    // Render the add button
-    let add_button_text = Span::styled("[     +     ]", Style::default().fg(Color::Green));
-    let add_button_paragraph = Paragraph::new(add_button_text).wrap(Wrap { trim: false });
-
-    f.render_widget(add_button_paragraph, chunks[todos.len()]);
+    // Pick up changes other programs made to the data file
+    changed |= check_for_changes(app);
+    // Write changes once they have settled, which hides the unsaved indicator
+    if app.needs_save() {
+        flush_todos(app);
+        changed = true;
+    }
+    changed
 }
 
-// Function to process mouse events
-fn process_mouse_event(
-    mouse_event: event::MouseEvent,
-    todos: &mut Vec<Todo>,
-    dragging: &mut bool,
-    drag_index: &mut Option<usize>,
-    chunks: &[Rect],
-    editing_index: &mut Option<usize>,
-    input_buffer: &mut String,
-    just_started_editing: &mut bool,
-) {
-    match mouse_event.kind {
-        MouseEventKind::Down(button) => {
-            if button == MouseButton::Left {
-                // Get the mouse position
-                let mouse_pos = (mouse_event.column, mouse_event.row);
-                let mut clicked_on_todo = false;
-                // Check if click is on any todo item
-                for (i, chunk) in chunks.iter().enumerate() {
-                    if i >= todos.len() {
-                        break;
-                    }
-                    if is_inside(mouse_pos, *chunk) {
-                        clicked_on_todo = true;
-
-                        // Split the line into title and progress bar
-                        let horizontal_chunks = Layout::default()
-                            .direction(Direction::Horizontal)
-                            .constraints(
-                                [
-                                    Constraint::Length(35), // Must match the ui function
-                                    Constraint::Min(1),     // Remaining space for progress bar
-                                ]
-                                .as_ref(),
-                            )
-                            .split(*chunk);
-
-                        // Further split the first chunk into checkbox and title
-                        let checkbox_and_title_chunks = Layout::default()
-                            .direction(Direction::Horizontal)
-                            .constraints(
-                                [
-                                    Constraint::Length(4), // For '[ ] '
-                                    Constraint::Min(1),    // Remaining space for title
-                                ]
-                                .as_ref(),
-                            )
-                            .split(horizontal_chunks[0]);
-
-                        if is_inside(mouse_pos, checkbox_and_title_chunks[0]) {
-                            // Clicked on the checkbox - toggle completed status
-                            todos[i].completed = !todos[i].completed;
-                            // Save the todos
-                            save_todos(&todos);
-                            return; // Exit function, no further processing needed
-                        } else if is_inside(mouse_pos, checkbox_and_title_chunks[1]) {
-                            // Clicked on the title area - start editing
-                            *editing_index = Some(i);
-                            *just_started_editing = true; // Indicate that we just entered edit mode
-                            if todos[i].name == "New Todo" {
-                                *input_buffer = String::new(); // Start with an empty input buffer
-                            } else {
-                                *input_buffer = todos[i].name.clone(); // Start with the existing name
-                            }
-                        } else if is_inside(mouse_pos, horizontal_chunks[1]) {
-                            // Clicked on the progress bar area
-                            // Start dragging to update progress
-                            *dragging = true;
-                            *drag_index = Some(i);
-                            update_progress(&mut todos[i], horizontal_chunks[1], mouse_event.column);
-                            // Save the todos after updating progress
-                            save_todos(&todos);
-                        }
-
-                        break; // We've found the clicked todo, so we can exit the loop
-                    }
-                }
-                // Check if click is on the add button
-                if !clicked_on_todo {
-                    if let Some(add_button_rect) = chunks.get(todos.len()) {
-                        if is_inside(mouse_pos, *add_button_rect) {
-                            // Add a new todo
-                            todos.push(Todo {
-                                name: String::from("New Todo"),
-                                progress: 0,
-                                completed: false, // Initialize as not completed
-                            });
-                            // Save the todos after adding a new one
-                            save_todos(&todos);
-                        }
-                    }
+// Function to forward terminal events to the main thread until it stops
+// the input thread or goes away
+fn read_input(tx: &mpsc::Sender<Event<CEvent>>, input: &InputControl) {
+    loop {
+        let reading = input.reading.lock().unwrap();
+        // Copied out, as waiting below takes the lock again
+        let state = *input.state.lock().unwrap();
+        match state {
+            InputState::Reading => {}
+            InputState::Paused => {
+                drop(reading);
+                if input.wait_while_paused() {
+                    continue;
                 }
+                return;
             }
+            InputState::Stopped => return,
         }
-        MouseEventKind::Drag(button) => {
-            if *editing_index == None && *dragging && button == MouseButton::Left {
-                if let Some(i) = *drag_index {
-                    let chunk = chunks[i];
-                    let horizontal_chunks = Layout::default()
-                        .direction(Direction::Horizontal)
-                        .constraints(
-                            [
-                                Constraint::Length(35), // Must match the ui function
-                                Constraint::Min(1),
-                            ]
-                            .as_ref(),
-                        )
-                        .split(chunk);
-
-                    update_progress(&mut todos[i], horizontal_chunks[1], mouse_event.column);
-                    // Save the todos after updating progress
-                    save_todos(&todos);
-                }
-            }
+        // Wait a while at most, so a pause or stop is noticed
+        if !event::poll(INPUT_WAIT).unwrap() {
+            continue;
         }
-        MouseEventKind::Up(button) => {
-            if button == MouseButton::Left {
-                *dragging = false;
-                *drag_index = None;
-            }
+        let event = event::read().unwrap();
+        if tx.send(Event::Input(event)).is_err() {
+            return;
         }
-        _ => {}
     }
 }
 
-// Function to update the progress of a todo based on mouse x position
-fn update_progress(todo: &mut Todo, area: Rect, mouse_x: u16) {
-    // Position of the '[' character
-    let percent_str = format!(" {}%", todo.progress);
-    let extra_chars = 2 + percent_str.len(); // '[' and ']' and percentage
-
-    if area.width <= extra_chars as u16 {
-        // Not enough space, do nothing
-        return;
-    }
+// Function to restore the terminal the way it was before the TUI started
+fn restore_terminal() -> io::Result<()> {
+    disable_raw_mode()?;
+    execute!(
+        io::stdout(),
+        LeaveAlternateScreen,
+        DisableMouseCapture,
+        DisableFocusChange,
+        cursor::Show
+    )
+}
 
-    // Calculate the width of the progress bar
-    let bar_width = area.width - extra_chars as u16;
+// Function to stop the process until the shell continues it, handing the
+// terminal back meanwhile. Unsaved changes are written first.
+fn suspend<B: Backend + io::Write>(
+    terminal: &mut Terminal<B>,
+    app: &mut App,
+    input: &InputControl,
+) -> io::Result<()> {
+    flush_todos(app);
+    let _guard = input.pause();
+    suspend_terminal(terminal)?;
+    // Stop the way SIGTSTP would, returning once we are continued
+    low_level::emulate_default_handler(SIGTSTP)?;
+    resume_terminal(terminal)?;
+    input.resume();
+    Ok(())
+}
 
-    // The progress bar starts after the '[' character
-    let progress_bar_start_x = area.x + 1; // Start after '['
-    let progress_bar_end_x = progress_bar_start_x + bar_width;
+// Function to hand the terminal over to another program, mirroring the cleanup in main
+fn suspend_terminal<B: Backend + io::Write>(terminal: &mut Terminal<B>) -> io::Result<()> {
+    disable_raw_mode()?;
+    execute!(
+        terminal.backend_mut(),
+        LeaveAlternateScreen,
+        DisableMouseCapture,
+        DisableFocusChange
+    )?;
+    terminal.show_cursor()
+}
 
-    if mouse_x >= progress_bar_start_x && mouse_x <= progress_bar_end_x {
-        let relative_x = mouse_x - progress_bar_start_x;
-        let progress = ((relative_x * 100) / bar_width).min(100) as u16;
-        if todo.progress != progress {
-            todo.progress = progress;
-        }
-    }
+// Function to take the terminal back after suspend_terminal
+fn resume_terminal<B: Backend + io::Write>(terminal: &mut Terminal<B>) -> io::Result<()> {
+    enable_raw_mode()?;
+    execute!(
+        terminal.backend_mut(),
+        EnterAlternateScreen,
+        EnableMouseCapture,
+        EnableFocusChange
+    )?;
+    // Force a full redraw on the next frame
+    terminal.clear()
 }
 
-// Function to build the ASCII progress bar
-fn build_progress_bar(progress: u16, width: usize) -> String {
-    // Width is the total width, we need to subtract for brackets and percentage
-    let percent_str = format!(" {}%", progress);
-    let extra_chars = 2 + percent_str.len(); // '[' and ']' and percentage
+// Function to ask for the passphrase of encrypted todos on a screen of its
+// own, before the todos are loaded. Returns false if the user gave up.
+fn unlock_screen(contents: &str) -> io::Result<bool> {
+    enable_raw_mode()?;
+    execute!(io::stdout(), EnterAlternateScreen)?;
+    let mut terminal = Terminal::new(CrosstermBackend::new(io::stdout()))?;
 
-    if width <= extra_chars {
-        // Not enough space to render progress bar
-        return format!("{}%", percent_str);
-    }
+    let mut passphrase = String::new();
+    let mut message: Option<&str> = None;
+    let unlocked = loop {
+        terminal.draw(|f| {
+            let area = app::centered_rect(50, 5, f.size());
+            let block = Block::default()
+                .title(" todos.json is encrypted ")
+                .borders(Borders::ALL);
+            let lines = vec![
+                Spans::from(format!("Passphrase: {}_", "*".repeat(passphrase.len()))),
+                Spans::from(Span::styled(
+                    message.unwrap_or("Enter to unlock, Esc to quit"),
+                    Style::default().fg(if message.is_some() {
+                        Color::Red
+                    } else {
+                        Color::DarkGray
+                    }),
+                )),
+            ];
+            f.render_widget(Paragraph::new(lines).block(block), area);
+        })?;
 
-    let bar_width = width - extra_chars;
+        if let CEvent::Key(key_event) = event::read()? {
+            match key_event.code {
+                KeyCode::Char('c') if key_event.modifiers.contains(KeyModifiers::CONTROL) => {
+                    break false;
+                }
+                KeyCode::Char(c) => passphrase.push(c),
+                KeyCode::Backspace => {
+                    passphrase.pop();
+                }
+                KeyCode::Enter => match crypto::unlock(contents, &passphrase) {
+                    Ok(()) => break true,
+                    Err(CryptoError::WrongPassphrase) => {
+                        passphrase.clear();
+                        message = Some("Wrong passphrase, try again");
+                    }
+                    Err(_) => break true, // Loading the todos reports the problem
+                },
+                KeyCode::Esc => break false,
+                _ => {}
+            }
+        }
+    };
 
-    let filled_blocks = (progress as usize * bar_width) / 100;
-    let empty_blocks = bar_width - filled_blocks;
-    format!(
-        "[{}{}]{}",
-        "#".repeat(filled_blocks),
-        "-".repeat(empty_blocks),
-        percent_str
-    )
+    disable_raw_mode()?;
+    execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+    Ok(unlocked)
 }
 
-// Helper function to compute chunks based on the terminal size and todos
-fn compute_chunks(size: Rect, todos: &[Todo]) -> Vec<Rect> {
-    let mut constraints: Vec<Constraint> = Vec::new();
+// Function to edit a todo's name and notes in $VISUAL or $EDITOR.
+// The temp file holds the name on the first line and the notes after a blank line.
+// Only the user can read it, and it is removed whatever happens, as the notes
+// may otherwise only be stored encrypted.
+fn edit_externally<B: Backend + io::Write>(
+    terminal: &mut Terminal<B>,
+    todo: &mut Todo,
+) -> io::Result<()> {
+    let (file_path, mut file) = create_temp_file()?;
+    let result = file
+        .write_all(format!("{}\n\n{}", todo.name, todo.notes).as_bytes())
+        .and_then(|_| {
+            drop(file);
+            run_editor(terminal, &file_path, todo)
+        });
+    let removed = fs::remove_file(&file_path);
+    result.and(removed)
+}
 
-    for _ in todos {
-        constraints.push(Constraint::Length(1)); // Each todo takes up 1 row
+// Helper function to create a temp file under a name no one can guess, so
+// it can't be swapped for a symlink beforehand
+fn create_temp_file() -> io::Result<(PathBuf, File)> {
+    loop {
+        let file_path = env::temp_dir().join(format!("ratodui-{:016x}.md", OsRng.next_u64()));
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&file_path);
+        match file {
+            Ok(file) => return Ok((file_path, file)),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
     }
-
-    // Add constraint for the add button
-    constraints.push(Constraint::Length(1));
-
-    Layout::default()
-        .direction(Direction::Vertical)
-        .margin(1) // Reduce margin to save space
-        .constraints(constraints)
-        .split(size)
-        .to_vec() // Convert Rc<[Rect]> to Vec<Rect>
 }
 
-// Helper function to check if a point is inside a rectangle
-fn is_inside(pos: (u16, u16), area: Rect) -> bool {
-    pos.0 >= area.x
-        && pos.0 < area.x + area.width
-        && pos.1 >= area.y
-        && pos.1 < area.y + area.height
+// Helper function to run the editor on the temp file and read back the result
+fn run_editor<B: Backend + io::Write>(
+    terminal: &mut Terminal<B>,
+    file_path: &Path,
+    todo: &mut Todo,
+) -> io::Result<()> {
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| String::from("vi"));
+    // The editor variable may contain arguments, e.g. "code --wait"
+    let mut parts = editor.split_whitespace();
+    let program = parts.next().unwrap_or("vi");
+
+    suspend_terminal(terminal)?;
+    let status = Command::new(program).args(parts).arg(file_path).status();
+    resume_terminal(terminal)?;
+
+    let status = status?;
+    if status.success() {
+        let contents = fs::read_to_string(file_path)?;
+        let (name, notes) = parse_edited_todo(&contents);
+        if !name.is_empty() {
+            todo.name = name;
+        }
+        todo.notes = notes;
+    }
+    Ok(())
 }
 
-// Function to load todos from a JSON file
-fn load_todos() -> Vec<Todo> {
-    let mut todos = Vec::new();
-
-    if let Some(proj_dirs) = ProjectDirs::from("com", "todo", "todo") {
-        let data_dir = proj_dirs.data_dir();
-        let file_path = data_dir.join("todos.json");
+// Helper function to split edited text into the name and the notes
+fn parse_edited_todo(contents: &str) -> (String, String) {
+    let (name, notes) = contents.split_once('\n').unwrap_or((contents, ""));
+    // Drop the blank separator line and any trailing newline added by the editor
+    let notes = notes.trim_start_matches('\n').trim_end();
+    (name.trim().to_string(), notes.to_string())
+}
 
-        if let Ok(contents) = fs::read_to_string(&file_path) {
-            if let Ok(loaded_todos) = serde_json::from_str::<Vec<Todo>>(&contents) {
-                todos = loaded_todos;
+// Function to run a command typed after ':' in the TUI, returning the message to show.
+// Imports are not merged right away but set up for a preview.
+fn run_command(
+    command: &str,
+    todos: &[Todo],
+    import_preview: &mut Option<ImportPreview>,
+) -> Result<String, String> {
+    let words: Vec<&str> = command.split_whitespace().collect();
+    match words.as_slice() {
+        ["export", format, path] => {
+            let archived = read_archive(None).map_err(|e| e.to_string())?;
+            let contents = formats::export(format, todos, &archived)?;
+            fs::write(expand_home(path), contents)
+                .map_err(|e| format!("Failed to write {}: {}", path, e))?;
+            Ok(format!("Exported {} todos to {}", todos.len(), path))
+        }
+        ["import", format, path] => {
+            let contents = fs::read_to_string(expand_home(path))
+                .map_err(|e| format!("Failed to read {}: {}", path, e))?;
+            let imported = formats::import(format, &contents)?;
+            if imported.is_empty() {
+                return Err(format!("No todos found in {}", path));
             }
+            let count = imported.len();
+            *import_preview = Some(ImportPreview {
+                source: path.to_string(),
+                todos: imported,
+                scroll: 0,
+            });
+            Ok(format!(
+                "{} todos to import: Enter to merge, Esc to cancel",
+                count
+            ))
+        }
+        ["bind", path] | ["bind", "--overwrite", path] => {
+            let overwrite = words[1] == "--overwrite";
+            bind_markdown(Some(&expand_home(path)), todos, overwrite)?;
+            Ok(format!("The list is now kept in sync with {}", path))
+        }
+        ["unbind"] => {
+            bind_markdown(None, todos, false)?;
+            Ok(String::from(
+                "The list is no longer bound to a markdown file",
+            ))
         }
+        [] => Ok(String::new()),
+        _ => Err(format!(
+            "Unknown command '{}' (try: import|export <{}> <file>, bind [--overwrite] <file.md>, unbind)",
+            command,
+            formats::FORMATS.join("|")
+        )),
     }
-
-    // Remove completed todos (filter out on startup)
-    todos.retain(|todo| !todo.completed);
-
-    todos
 }
 
-// Function to save todos to a JSON file
-fn save_todos(todos: &Vec<Todo>) {
-    if let Some(proj_dirs) = ProjectDirs::from("com", "todo", "todo") {
-        let data_dir = proj_dirs.data_dir();
+// Helper function to expand a leading '~' in a path typed by the user
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), env::var_os("HOME")) {
+        (Some(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => PathBuf::from(path),
+    }
+}
 
-        // Create directories if they don't exist
-        if let Err(e) = fs::create_dir_all(&data_dir) {
-            eprintln!("Failed to create data directory: {}", e);
-            return;
+// Function to look for changes other programs made to the data file since
+// the last check. Changes that merge cleanly are taken over right away;
+// otherwise the user is asked to resolve the conflicts first. Returns whether
+// the data file had changed.
+fn check_for_changes(app: &mut App) -> bool {
+    if app.reload.is_some() {
+        return false;
+    }
+    let stamp = data_file_stamp();
+    if stamp == app.file_stamp {
+        return false;
+    }
+    app.file_stamp = stamp;
+
+    match read_external_changes(&app.base, &app.todos) {
+        Ok(Some(changes)) if changes.merged.conflicts.is_empty() => {
+            // Our own saves show up here too, without anything to take over
+            if changes.merged.todos != app.todos {
+                app.status_message =
+                    Some(String::from("Reloaded the todos saved by another program"));
+            }
+            app.finish_reload(changes);
         }
+        Ok(Some(changes)) => app.start_reload(changes),
+        Ok(None) => {}
+        Err(e) => app.status_message = Some(e),
+    }
+    true
+}
 
-        let file_path = data_dir.join("todos.json");
+// Function to read the stored todos after another program changed them and
+// merge them with ours. Returns None if the data file has gone away.
+fn read_external_changes(base: &[Todo], todos: &[Todo]) -> Result<Option<Reload>, String> {
+    let storage = storage::configured().map_err(|e| e.to_string())?;
+    if storage.stamp().is_none() {
+        return Ok(None);
+    }
+    let theirs = storage
+        .read()
+        .map_err(|e| format!("Not reloading the todos: {}", e))?;
+    let merged = merge::merge(base, todos, &theirs);
+    Ok(Some(Reload { merged, theirs }))
+}
 
-        match serde_json::to_string_pretty(&todos) {
-            Ok(json) => {
-                if let Err(e) = fs::write(&file_path, json) {
-                    eprintln!("Failed to write to file: {}", e);
-                }
-            }
-            Err(e) => {
-                eprintln!("Failed to serialize todos: {}", e);
-            }
-        }
+// Function to write the todos if they have unsaved changes, unless another
+// ratodui is editing them or the changes of another program still have to be
+// merged in. If that fails they stay unsaved and are tried again after
+// SAVE_DELAY.
+fn flush_todos(app: &mut App) {
+    if app.unsaved.is_none() || app.read_only || app.reload.is_some() {
+        return;
     }
-}
\ No newline at end of file
+    app.unsaved = match save_todos(&app.todos) {
+        Ok(true) => None,
+        Ok(false) => Some(Instant::now()), // Merged with the other program's save first
+        Err(e) if e.todos_saved() => {
+            app.status_message = Some(format!("Saved the todos, but {}", e));
+            None
+        }
+        Err(e) => {
+            app.status_message = Some(format!("Failed to save todos: {}", e));
+            Some(Instant::now())
+        }
+    };
+}
@@ -0,0 +1,152 @@
+// src/markdown.rs
+
+use crate::Todo;
+
+// Export todos as a markdown checklist, e.g. "- [ ] Write docs (40%)"
+pub fn export(todos: &[Todo]) -> String {
+    let mut out = String::new();
+    for todo in todos {
+        let checkbox = if todo.completed { "[x]" } else { "[ ]" };
+        if todo.progress > 0 {
+            out.push_str(&format!(
+                "- {} {} ({}%)\n",
+                checkbox, todo.name, todo.progress
+            ));
+        } else {
+            out.push_str(&format!("- {} {}\n", checkbox, todo.name));
+        }
+    }
+    out
+}
+
+// Import all checklist items from a markdown file. Nested items are
+// imported as todos of their own.
+pub fn import(contents: &str) -> Vec<Todo> {
+    contents.lines().filter_map(parse_item).collect()
+}
+
+// Replace the first checklist in a markdown file with the todos, keeping
+// everything around it. Without a checklist the todos are appended.
+pub fn rewrite(contents: &str, todos: &[Todo]) -> String {
+    let lines: Vec<&str> = contents.lines().collect();
+    let checklist = export(todos);
+
+    let Some((start, end)) = find_checklist(&lines) else {
+        let mut out = contents.to_string();
+        if !out.is_empty() && !out.ends_with('\n') {
+            out.push('\n');
+        }
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        out.push_str(&checklist);
+        return out;
+    };
+
+    let mut out = String::new();
+    for line in &lines[..start] {
+        out.push_str(line);
+        out.push('\n');
+    }
+    out.push_str(&checklist);
+    for line in &lines[end..] {
+        out.push_str(line);
+        out.push('\n');
+    }
+    out
+}
+
+// Whether the first checklist in a markdown file has anything that isn't
+// what `rewrite` would put there, i.e. what binding the file would lose
+pub fn checklist_differs(contents: &str, todos: &[Todo]) -> bool {
+    let lines: Vec<&str> = contents.lines().collect();
+    let Some((start, end)) = find_checklist(&lines) else {
+        return false;
+    };
+    let items = lines[start..end]
+        .iter()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| parse_item(line))
+        .collect::<Option<Vec<_>>>();
+    // Lines below an item that aren't items themselves are lost as well
+    items.is_none_or(|items| export(&items) != export(todos))
+}
+
+// Helper function to find the lines of the first checklist. It ends after
+// the last item or line indented below one that comes before anything else;
+// blank lines between items are part of it.
+fn find_checklist(lines: &[&str]) -> Option<(usize, usize)> {
+    let start = lines.iter().position(|line| parse_item(line).is_some())?;
+    let mut end = start + 1;
+    for (i, line) in lines.iter().enumerate().skip(end) {
+        if parse_item(line).is_some() || (line.starts_with([' ', '\t']) && !line.trim().is_empty())
+        {
+            end = i + 1;
+        } else if !line.trim().is_empty() {
+            break;
+        }
+    }
+    Some((start, end))
+}
+
+// Parse a line like "- [x] Write docs (40%)" into a todo
+fn parse_item(line: &str) -> Option<Todo> {
+    let rest = line.trim_start().strip_prefix(['-', '*', '+'])?;
+    let rest = rest.strip_prefix(' ')?;
+    let completed = match rest.get(..3)? {
+        "[ ]" => false,
+        "[x]" | "[X]" => true,
+        _ => return None,
+    };
+    let text = rest[3..].trim();
+
+    let mut todo = Todo::new(text);
+    todo.set_completed(completed);
+    if let Some((name, progress)) = split_progress(text) {
+        todo.name = name.to_string();
+        todo.progress = progress;
+    }
+    Some(todo)
+}
+
+// Split a trailing "(40%)" off the text of an item
+fn split_progress(text: &str) -> Option<(&str, u16)> {
+    let (name, progress) = text.strip_suffix("%)")?.rsplit_once('(')?;
+    let progress = progress.parse::<u16>().ok().filter(|p| *p <= 100)?;
+    Some((name.trim_end(), progress))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rewriting_replaces_only_the_first_checklist() {
+        let todos = vec![Todo::new("One"), Todo::new("Two")];
+        let contents = "# Plans\n\nIntro\n\n- [ ] a\n  details\n\n- [x] b\n\nOutro\n- [ ] c\n";
+        assert_eq!(
+            rewrite(contents, &todos),
+            "# Plans\n\nIntro\n\n- [ ] One\n- [ ] Two\n\nOutro\n- [ ] c\n"
+        );
+        assert_eq!(
+            rewrite("# Plans", &todos),
+            "# Plans\n\n- [ ] One\n- [ ] Two\n"
+        );
+    }
+
+    #[test]
+    fn only_checklists_with_other_items_differ() {
+        let todos = vec![Todo::new("One"), Todo::new("Two")];
+        assert!(!checklist_differs("# Plans\n", &todos));
+        assert!(!checklist_differs(
+            "Intro\n- [ ] One\n- [ ] Two\n\nOutro\n",
+            &todos
+        ));
+        assert!(!checklist_differs("- [ ] One\n\n- [ ] Two\n", &todos));
+        assert!(checklist_differs("- [ ] One\n\n- [ ] Three\n", &todos));
+        assert!(checklist_differs(
+            "- [ ] One\n  details\n- [ ] Two\n",
+            &todos
+        ));
+    }
+}
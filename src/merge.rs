@@ -0,0 +1,228 @@
+// src/merge.rs
+
+//! Three-way merge of todo lists changed by two programs.
+
+use crate::Todo;
+
+/// A todo that was changed on both sides in different ways, or changed on
+/// one side and removed on the other
+pub struct Conflict {
+    /// ID of the todo on both sides
+    pub id: u64,
+    /// Our version, or None if we removed the todo
+    pub mine: Option<Todo>,
+    /// Their version, or None if they removed the todo
+    pub theirs: Option<Todo>,
+}
+
+/// The result of [`merge`]: the merged list, and the todos whose two
+/// versions the user has to choose between
+pub struct Merged {
+    /// Our version of each conflicting todo until resolved
+    pub todos: Vec<Todo>,
+    /// Todos to settle with [`resolve`]
+    pub conflicts: Vec<Conflict>,
+}
+
+/// Three-way merge of two lists that both started out as `base`. Todos
+/// changed on only one side take that change; todos added on either side are
+/// kept, in our order followed by the new ones from `theirs`.
+pub fn merge(base: &[Todo], mine: &[Todo], theirs: &[Todo]) -> Merged {
+    let find = |todos: &[Todo], id: u64| todos.iter().find(|todo| todo.id == id).cloned();
+    let mut todos = Vec::new();
+    let mut conflicts = Vec::new();
+    let mut renumbered = Vec::new();
+
+    for todo in mine {
+        let original = find(base, todo.id);
+        let other = find(theirs, todo.id);
+        match (original, other) {
+            // Added on both sides under the same ID: keep both
+            (None, Some(other)) if other != *todo => {
+                todos.push(other);
+                renumbered.push(todo.clone());
+            }
+            (None, _) => todos.push(todo.clone()),
+            (Some(original), other) if original == *todo => {
+                // Unchanged here, so whatever happened there wins
+                todos.extend(other);
+            }
+            (Some(original), Some(other)) if original == other || other == *todo => {
+                todos.push(todo.clone());
+            }
+            (_, other) => {
+                todos.push(todo.clone());
+                conflicts.push(Conflict {
+                    id: todo.id,
+                    mine: Some(todo.clone()),
+                    theirs: other,
+                });
+            }
+        }
+    }
+
+    for todo in theirs {
+        if mine.iter().any(|other| other.id == todo.id) {
+            continue;
+        }
+        match find(base, todo.id) {
+            None => todos.push(todo.clone()),         // Added there
+            Some(original) if original == *todo => {} // Removed here
+            Some(_) => conflicts.push(Conflict {
+                id: todo.id,
+                mine: None,
+                theirs: Some(todo.clone()),
+            }),
+        }
+    }
+
+    // Our todos that were blocked by a renumbered todo follow it to its new ID
+    let mut new_ids = Vec::new();
+    for mut todo in renumbered {
+        let id = todos
+            .iter()
+            .chain(theirs)
+            .map(|todo| todo.id)
+            .max()
+            .unwrap_or(0)
+            + 1;
+        new_ids.push((todo.id, id));
+        todo.id = id;
+        todos.push(todo);
+    }
+    let renumber = |todo: &mut Todo| {
+        for blocker in &mut todo.blocked_by {
+            if let Some(&(_, id)) = new_ids.iter().find(|(old, _)| old == blocker) {
+                *blocker = id;
+            }
+        }
+    };
+    for todo in &mut todos {
+        let ours = mine.iter().any(|other| other == todo);
+        let theirs = theirs.iter().any(|other| other == todo);
+        if ours && !theirs || new_ids.iter().any(|&(_, id)| id == todo.id) {
+            renumber(todo);
+        }
+    }
+    for conflict in &mut conflicts {
+        conflict.mine.iter_mut().for_each(renumber);
+    }
+
+    Merged { todos, conflicts }
+}
+
+/// Settle a conflict in favor of one side
+pub fn resolve(todos: &mut Vec<Todo>, conflict: Conflict, take_theirs: bool) {
+    if !take_theirs {
+        return; // Our version is already in the list
+    }
+    let position = todos.iter().position(|todo| todo.id == conflict.id);
+    match (position, conflict.theirs) {
+        (Some(i), Some(theirs)) => todos[i] = theirs,
+        (Some(i), None) => {
+            todos.remove(i);
+        }
+        (None, Some(theirs)) => todos.push(theirs),
+        (None, None) => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn todo(id: u64, name: &str) -> Todo {
+        Todo {
+            id,
+            created_at: None,
+            ..Todo::new(name)
+        }
+    }
+
+    fn names(todos: &[Todo]) -> Vec<(u64, &str)> {
+        todos
+            .iter()
+            .map(|todo| (todo.id, todo.name.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn changes_on_one_side_are_taken() {
+        let base = [todo(1, "a"), todo(2, "b")];
+        let mine = [todo(1, "a"), todo(2, "b"), todo(3, "c")];
+        let theirs = [todo(1, "a2"), todo(2, "b")];
+        let merged = merge(&base, &mine, &theirs);
+        assert_eq!(names(&merged.todos), [(1, "a2"), (2, "b"), (3, "c")]);
+        assert!(merged.conflicts.is_empty());
+
+        // Removed on one side, unchanged on the other
+        let merged = merge(&base, &mine[..1], &base);
+        assert_eq!(names(&merged.todos), [(1, "a")]);
+        let merged = merge(&base, &base, &base[1..]);
+        assert_eq!(names(&merged.todos), [(2, "b")]);
+    }
+
+    #[test]
+    fn changes_on_both_sides_conflict_unless_equal() {
+        let base = [todo(1, "a")];
+        let merged = merge(&base, &[todo(1, "same")], &[todo(1, "same")]);
+        assert_eq!(names(&merged.todos), [(1, "same")]);
+        assert!(merged.conflicts.is_empty());
+
+        let mut merged = merge(&base, &[todo(1, "mine")], &[todo(1, "theirs")]);
+        assert_eq!(names(&merged.todos), [(1, "mine")]);
+        assert_eq!(merged.conflicts.len(), 1);
+        let conflict = merged.conflicts.remove(0);
+        assert_eq!(conflict.id, 1);
+        resolve(&mut merged.todos, conflict, true);
+        assert_eq!(names(&merged.todos), [(1, "theirs")]);
+    }
+
+    #[test]
+    fn changes_to_todos_removed_on_the_other_side_conflict() {
+        let base = [todo(1, "a"), todo(2, "b")];
+
+        let mut merged = merge(&base, &[todo(1, "a"), todo(2, "b2")], &base[..1]);
+        assert_eq!(names(&merged.todos), [(1, "a"), (2, "b2")]);
+        let conflict = merged.conflicts.remove(0);
+        assert!(conflict.mine.is_some() && conflict.theirs.is_none());
+        resolve(&mut merged.todos, conflict, true);
+        assert_eq!(names(&merged.todos), [(1, "a")]);
+
+        let mut merged = merge(&base, &base[..1], &[todo(1, "a"), todo(2, "b2")]);
+        assert_eq!(names(&merged.todos), [(1, "a")]);
+        let conflict = merged.conflicts.remove(0);
+        assert!(conflict.mine.is_none() && conflict.theirs.is_some());
+        resolve(&mut merged.todos, conflict, true);
+        assert_eq!(names(&merged.todos), [(1, "a"), (2, "b2")]);
+    }
+
+    #[test]
+    fn todos_added_on_both_sides_are_both_kept() {
+        let base = [todo(1, "a")];
+        let blocked = |id, name, blocker| Todo {
+            blocked_by: vec![blocker],
+            ..todo(id, name)
+        };
+        let mine = [todo(1, "a"), todo(2, "mine"), blocked(3, "after mine", 2)];
+        let theirs = [
+            todo(1, "a"),
+            todo(2, "theirs"),
+            blocked(4, "after theirs", 2),
+        ];
+        let merged = merge(&base, &mine, &theirs);
+        assert_eq!(
+            names(&merged.todos),
+            [
+                (1, "a"),
+                (2, "theirs"),
+                (3, "after mine"),
+                (4, "after theirs"),
+                (5, "mine")
+            ]
+        );
+        assert!(merged.conflicts.is_empty());
+        assert_eq!(merged.todos[2].blocked_by, [5]);
+        assert_eq!(merged.todos[3].blocked_by, [2]);
+    }
+}
@@ -0,0 +1,311 @@
+// src/org.rs
+
+use crate::{recurrence::Recurrence, Todo};
+use chrono::{NaiveDate, NaiveDateTime};
+
+// Keywords used when the file doesn't declare its own with #+TODO
+const OPEN_KEYWORDS: &[&str] = &["TODO", "NEXT", "STARTED", "WAITING", "HOLD"];
+const DONE_KEYWORDS: &[&str] = &["DONE", "CANCELLED", "CANCELED"];
+
+// Import the headings of an Org-mode file that have a TODO keyword.
+// Tags are inherited from parent headings, and a task with subtasks is
+// blocked by them, so it can only be completed after them. Cancelled tasks
+// are skipped.
+pub fn import(contents: &str) -> Vec<Todo> {
+    let (open_keywords, done_keywords) = keywords(contents);
+    let mut todos: Vec<Todo> = Vec::new();
+    // Headings above the current line: their level, tags and task index
+    let mut parents: Vec<(usize, Vec<String>, Option<usize>)> = Vec::new();
+    let mut current: Option<usize> = None;
+    let mut in_drawer = false;
+
+    for line in contents.lines() {
+        if let Some(heading) = parse_heading(line, &open_keywords, &done_keywords) {
+            in_drawer = false;
+            parents.retain(|(level, _, _)| *level < heading.level);
+            let mut tags: Vec<String> = parents
+                .iter()
+                .flat_map(|(_, tags, _)| tags.iter().cloned())
+                .collect();
+            for tag in &heading.tags {
+                if !tags.contains(tag) {
+                    tags.push(tag.clone());
+                }
+            }
+
+            current = None;
+            if let (Some(mut todo), false) = (heading.todo, heading.cancelled) {
+                todo.tags = tags;
+                todo.id = todos.len() as u64 + 1;
+                if let Some(parent) = parents.iter().rev().find_map(|(_, _, task)| *task) {
+                    todos[parent].blocked_by.push(todo.id);
+                }
+                current = Some(todos.len());
+                todos.push(todo);
+            }
+            parents.push((heading.level, heading.tags, current));
+            continue;
+        }
+
+        let Some(todo) = current.map(|i| &mut todos[i]) else {
+            continue;
+        };
+        let trimmed = line.trim();
+        if in_drawer {
+            in_drawer = !trimmed.eq_ignore_ascii_case(":END:");
+        } else if trimmed.starts_with(':') && trimmed.ends_with(':') && trimmed.len() > 1 {
+            in_drawer = true; // :PROPERTIES:, :LOGBOOK: and the like
+        } else if !parse_planning(todo, trimmed) && (!todo.notes.is_empty() || !trimmed.is_empty())
+        {
+            todo.notes.push_str(trimmed);
+            todo.notes.push('\n');
+        }
+    }
+
+    for todo in &mut todos {
+        todo.notes = todo.notes.trim_end().to_string();
+    }
+    todos
+}
+
+struct Heading {
+    level: usize,
+    tags: Vec<String>,
+    todo: Option<Todo>, // Set when the heading has a TODO keyword
+    cancelled: bool,
+}
+
+// Parse a heading like "** TODO [#A] Write docs [2/4] :work:docs:"
+fn parse_heading(
+    line: &str,
+    open_keywords: &[String],
+    done_keywords: &[String],
+) -> Option<Heading> {
+    let level = line.chars().take_while(|c| *c == '*').count();
+    if level == 0 {
+        return None;
+    }
+    let rest = line[level..].strip_prefix(' ')?.trim();
+
+    let mut words: Vec<&str> = rest.split_whitespace().collect();
+    let mut tags = Vec::new();
+    if let Some(last) = words.last() {
+        if last.len() > 1 && last.starts_with(':') && last.ends_with(':') {
+            tags = last
+                .split(':')
+                .filter(|tag| !tag.is_empty())
+                .map(String::from)
+                .collect();
+            words.pop();
+        }
+    }
+
+    let keyword = words.first().copied().unwrap_or("");
+    let done = done_keywords.iter().any(|k| k == keyword);
+    if !done && !open_keywords.iter().any(|k| k == keyword) {
+        return Some(Heading {
+            level,
+            tags,
+            todo: None,
+            cancelled: false,
+        });
+    }
+    words.remove(0);
+
+    let mut todo = Todo::new("");
+    todo.created_at = None;
+    todo.set_completed(done);
+    todo.completed_at = None; // Only known from a CLOSED line
+    if let Some(priority) = words.first().and_then(|word| word.strip_prefix("[#")) {
+        if let Some(priority) = priority.strip_suffix(']').and_then(|p| p.chars().next()) {
+            todo.priority = Some(priority.to_ascii_uppercase());
+            words.remove(0);
+        }
+    }
+    words.retain(|word| match parse_cookie(word) {
+        Some(progress) => {
+            todo.progress = progress;
+            false
+        }
+        None => true,
+    });
+    todo.name = words.join(" ");
+
+    Some(Heading {
+        level,
+        tags,
+        todo: Some(todo),
+        cancelled: keyword.starts_with("CANCEL"),
+    })
+}
+
+// Progress cookies are "[50%]" or "[2/4]"
+fn parse_cookie(word: &str) -> Option<u16> {
+    let cookie = word.strip_prefix('[')?.strip_suffix(']')?;
+    if let Some(percent) = cookie.strip_suffix('%') {
+        return percent.parse::<u16>().ok().map(|p| p.min(100));
+    }
+    let (done, total) = cookie.split_once('/')?;
+    let (done, total): (u64, u64) = (done.parse().ok()?, total.parse().ok()?);
+    let percent = (done.min(total) * 100).checked_div(total).unwrap_or(0);
+    Some(percent.min(100) as u16)
+}
+
+// Apply a planning line like "DEADLINE: <2024-03-01 Fri +1w> CLOSED: [...]".
+// A deadline wins over a scheduled date as the due date.
+fn parse_planning(todo: &mut Todo, line: &str) -> bool {
+    let keywords = ["DEADLINE:", "SCHEDULED:", "CLOSED:"];
+    if !keywords.iter().any(|keyword| line.starts_with(keyword)) {
+        return false;
+    }
+
+    let mut deadline = false;
+    for keyword in keywords {
+        let Some(start) = line.find(keyword) else {
+            continue;
+        };
+        let timestamp = line[start + keyword.len()..].trim_start();
+        let Some(timestamp) = timestamp.strip_prefix(['<', '[']) else {
+            continue;
+        };
+        let Some(end) = timestamp.find(['>', ']']) else {
+            continue;
+        };
+        let parts: Vec<&str> = timestamp[..end].split_whitespace().collect();
+        let Some(date) = parts
+            .first()
+            .and_then(|date| NaiveDate::parse_from_str(date, "%Y-%m-%d").ok())
+        else {
+            continue;
+        };
+
+        match keyword {
+            "CLOSED:" => {
+                let time = parts
+                    .iter()
+                    .find_map(|part| {
+                        NaiveDateTime::parse_from_str(
+                            &format!("{} {}", date, part),
+                            "%Y-%m-%d %H:%M",
+                        )
+                        .ok()
+                    })
+                    .or_else(|| date.and_hms_opt(0, 0, 0));
+                todo.completed_at = time.map(|time| time.and_utc());
+            }
+            _ if deadline => {}
+            _ => {
+                deadline = keyword == "DEADLINE:";
+                todo.due = Some(date);
+                if let Some(recurrence) = parts.iter().find_map(|part| parse_repeater(part)) {
+                    todo.recurrence = Some(recurrence);
+                }
+            }
+        }
+    }
+    true
+}
+
+// Repeaters: "+1w" and "++1w" follow the date, ".+3d" counts from completion
+fn parse_repeater(part: &str) -> Option<Recurrence> {
+    let (after_completion, rule) = match part.strip_prefix(".+") {
+        Some(rule) => (true, rule),
+        None => (
+            false,
+            part.strip_prefix("++").or_else(|| part.strip_prefix('+'))?,
+        ),
+    };
+    let unit = rule.chars().last()?;
+    let count: u32 = rule[..rule.len() - unit.len_utf8()].parse().ok()?;
+    // Repeaters too long to count in days are left out
+    let days = match unit {
+        'd' => count,
+        'w' => count.checked_mul(7)?,
+        'm' => count.checked_mul(30)?,
+        'y' => count.checked_mul(365)?,
+        _ => return None,
+    };
+
+    let recurrence = match (after_completion, unit, count) {
+        (true, _, _) => Recurrence::AfterCompletion { days },
+        (false, 'd', 1) => Recurrence::Day,
+        (false, 'w', 1) => Recurrence::Week { days: Vec::new() },
+        (false, 'm', 1) => Recurrence::Month,
+        _ => Recurrence::Days { days },
+    };
+    Some(recurrence)
+}
+
+// Read the keywords from "#+TODO: TODO NEXT | DONE" lines, where the ones
+// after the bar are done states; without a bar only the last one is
+fn keywords(contents: &str) -> (Vec<String>, Vec<String>) {
+    let mut open = Vec::new();
+    let mut done = Vec::new();
+    for line in contents.lines() {
+        let line = line.trim();
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        if !matches!(
+            key.to_uppercase().as_str(),
+            "#+TODO" | "#+SEQ_TODO" | "#+TYP_TODO"
+        ) {
+            continue;
+        }
+        // Drop fast access keys like "TODO(t)"
+        let words: Vec<String> = value
+            .split_whitespace()
+            .map(|word| word.split('(').next().unwrap_or(word).to_string())
+            .collect();
+        match words.iter().position(|word| word == "|") {
+            Some(bar) => {
+                open.extend_from_slice(&words[..bar]);
+                done.extend_from_slice(&words[bar + 1..]);
+            }
+            None => {
+                if let Some((last, rest)) = words.split_last() {
+                    open.extend_from_slice(rest);
+                    done.push(last.clone());
+                }
+            }
+        }
+    }
+
+    if open.is_empty() && done.is_empty() {
+        open = OPEN_KEYWORDS.iter().map(|k| k.to_string()).collect();
+        done = DONE_KEYWORDS.iter().map(|k| k.to_string()).collect();
+    }
+    (open, done)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn large_cookies_and_repeaters_dont_overflow() {
+        assert_eq!(parse_cookie("[700/800]"), Some(87));
+        assert_eq!(parse_cookie("[1000/1000]"), Some(100));
+        assert_eq!(parse_cookie("[9999%]"), Some(100));
+        assert_eq!(
+            parse_repeater("+3000w"),
+            Some(Recurrence::Days { days: 21000 })
+        );
+        assert_eq!(parse_repeater("+4000000000y"), None);
+
+        let todos = import("* TODO Big [700/800]\n  DEADLINE: <2024-03-01 Fri .+4000000000m>\n");
+        assert_eq!(todos[0].progress, 87);
+        assert_eq!(todos[0].recurrence, None);
+    }
+
+    #[test]
+    fn malformed_planning_lines_are_skipped() {
+        for planning in ["DEADLINE: >", "DEADLINE: \u{e9}>", "DEADLINE: 2024-03-01>"] {
+            let todos = import(&format!("* TODO a\n{}\n", planning));
+            assert_eq!(todos.len(), 1);
+            assert_eq!(todos[0].due, None);
+        }
+        let todos = import("* TODO a\nDEADLINE: [2024-03-01 Fri]\n");
+        assert_eq!(todos[0].due, NaiveDate::from_ymd_opt(2024, 3, 1));
+    }
+}
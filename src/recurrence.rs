@@ -0,0 +1,160 @@
+// src/recurrence.rs
+
+//! Rules for todos that come back after they are completed.
+
+use chrono::{Datelike, Days, Months, NaiveDate, Weekday};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// How a todo repeats once it has been completed
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(tag = "every", rename_all = "snake_case")]
+pub enum Recurrence {
+    /// Every day
+    Day,
+    /// Monday to Friday
+    Weekday,
+    /// On the given days, or a week later if empty
+    Week {
+        /// Days of the week the todo is due on
+        days: Vec<Weekday>,
+    },
+    /// Same day of the month as the due date
+    Month,
+    /// Every N days after the due date
+    Days {
+        /// Days from one due date to the next
+        days: u32,
+    },
+    /// N days after the todo was actually completed
+    AfterCompletion {
+        /// Days from the completion to the next due date
+        days: u32,
+    },
+}
+
+impl Recurrence {
+    /// Compute the due date of the next occurrence. Schedule-based rules step
+    /// from the previous due date and skip occurrences that are already past.
+    /// Returns None if the next occurrence is past the last date there is.
+    pub fn next_due(&self, due: Option<NaiveDate>, completed_on: NaiveDate) -> Option<NaiveDate> {
+        if let Recurrence::AfterCompletion { days } = self {
+            return completed_on.checked_add_days(Days::new((*days).max(1) as u64));
+        }
+
+        let mut next = due.unwrap_or(completed_on);
+        loop {
+            next = self.step(next)?;
+            if next > completed_on {
+                return Some(next);
+            }
+        }
+    }
+
+    fn step(&self, date: NaiveDate) -> Option<NaiveDate> {
+        match self {
+            Recurrence::Day => date.checked_add_days(Days::new(1)),
+            Recurrence::Weekday => {
+                next_matching(date, |day| !matches!(day, Weekday::Sat | Weekday::Sun))
+            }
+            Recurrence::Week { days } if !days.is_empty() => {
+                next_matching(date, |day| days.contains(&day))
+            }
+            Recurrence::Week { .. } => date.checked_add_days(Days::new(7)),
+            Recurrence::Month => date
+                .checked_add_months(Months::new(1))
+                .or_else(|| date.checked_add_days(Days::new(30))),
+            Recurrence::Days { days } | Recurrence::AfterCompletion { days } => {
+                date.checked_add_days(Days::new((*days).max(1) as u64))
+            }
+        }
+    }
+
+    /// Parse a rule as typed in the details popup, e.g. "daily", "weekdays",
+    /// "weekly mon,thu", "monthly", "every 3 days" or "after 7 days"
+    pub fn parse(input: &str) -> Option<Recurrence> {
+        let input = input.trim().to_lowercase();
+        let mut words = input.split_whitespace();
+        let rule = match words.next()? {
+            "daily" => Recurrence::Day,
+            "weekdays" => Recurrence::Weekday,
+            "weekly" => {
+                let days = words
+                    .flat_map(|word| word.split(','))
+                    .filter(|day| !day.is_empty())
+                    .map(|day| day.parse::<Weekday>().ok())
+                    .collect::<Option<Vec<_>>>()?;
+                return Some(Recurrence::Week { days });
+            }
+            "monthly" => Recurrence::Month,
+            "every" => Recurrence::Days {
+                days: parse_days(words)?,
+            },
+            "after" => Recurrence::AfterCompletion {
+                days: parse_days(words)?,
+            },
+            _ => return None,
+        };
+        Some(rule)
+    }
+}
+
+impl fmt::Display for Recurrence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Recurrence::Day => write!(f, "daily"),
+            Recurrence::Weekday => write!(f, "weekdays"),
+            Recurrence::Week { days } if days.is_empty() => write!(f, "weekly"),
+            Recurrence::Week { days } => {
+                let days: Vec<String> = days
+                    .iter()
+                    .map(|day| day.to_string().to_lowercase())
+                    .collect();
+                write!(f, "weekly {}", days.join(","))
+            }
+            Recurrence::Month => write!(f, "monthly"),
+            Recurrence::Days { days } => write!(f, "every {} days", days),
+            Recurrence::AfterCompletion { days } => write!(f, "after {} days", days),
+        }
+    }
+}
+
+// Helper function to find the first day after `date` whose weekday matches
+fn next_matching(date: NaiveDate, matches: impl Fn(Weekday) -> bool) -> Option<NaiveDate> {
+    let mut next = date.checked_add_days(Days::new(1))?;
+    while !matches(next.weekday()) {
+        next = next.checked_add_days(Days::new(1))?;
+    }
+    Some(next)
+}
+
+// Helper function to parse "3", "3 days" or "3d"
+fn parse_days<'a>(mut words: impl Iterator<Item = &'a str>) -> Option<u32> {
+    let count = words.next()?.trim_end_matches('d');
+    match count.parse() {
+        Ok(days) if days > 0 => Some(days),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn occurrences_past_the_last_date_are_none() {
+        let today = NaiveDate::from_ymd_opt(2024, 3, 1).unwrap();
+        let rule = Recurrence::parse("every 4000000000 days").unwrap();
+        assert_eq!(rule.next_due(Some(today), today), None);
+        let rule = Recurrence::parse("after 4000000000 days").unwrap();
+        assert_eq!(rule.next_due(None, today), None);
+        assert_eq!(Recurrence::Day.next_due(None, NaiveDate::MAX), None);
+
+        assert_eq!(
+            Recurrence::parse("every 3 days")
+                .unwrap()
+                .next_due(Some(today), today),
+            NaiveDate::from_ymd_opt(2024, 3, 4)
+        );
+    }
+}
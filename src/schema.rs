@@ -0,0 +1,293 @@
+// src/schema.rs
+
+//! The layout of todos.json and migrations from older versions of it.
+
+use crate::Todo;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+/// Version of the todos.json layout written by this build
+pub const CURRENT_VERSION: u64 = 2;
+
+// Upgrades from each older version to the next one; MIGRATIONS[0] turns a
+// version 1 file into version 2 and so on
+const MIGRATIONS: [fn(Value) -> Value; 1] = [v1_to_v2];
+
+// The parts of todos.json this build reads. The file also holds the version,
+// the revision, the next todo ID, the list names and settings, which `update`
+// keeps as they are or counts up.
+#[derive(Deserialize)]
+struct DataFile {
+    #[serde(default)]
+    lists: Vec<TodoList>,
+}
+
+#[derive(Deserialize)]
+struct TodoList {
+    #[serde(default)]
+    todos: Vec<Todo>,
+    #[serde(default)]
+    /// Completed todos cleared from the list
+    archive: Vec<Todo>,
+}
+
+/// Why todos.json could not be read or updated
+#[derive(Debug)]
+pub enum SchemaError {
+    /// Written by a newer version of ratodui
+    TooNew(u64),
+    /// Not valid JSON, or not laid out as expected
+    Invalid(serde_json::Error),
+}
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SchemaError::TooNew(version) => write!(
+                f,
+                "todos.json has version {} but this ratodui only understands up to version {}; \
+                 please upgrade ratodui",
+                version, CURRENT_VERSION
+            ),
+            SchemaError::Invalid(e) => write!(f, "todos.json is not valid: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for SchemaError {}
+
+impl From<serde_json::Error> for SchemaError {
+    fn from(e: serde_json::Error) -> Self {
+        SchemaError::Invalid(e)
+    }
+}
+
+/// Parse the contents of todos.json, upgrading older versions on the way.
+/// Returns the todos of the first list and the version the file was stored in.
+pub fn parse(contents: &str) -> Result<(Vec<Todo>, u64), SchemaError> {
+    let (list, version) = parse_first_list(contents)?;
+    Ok((list.map(|list| list.todos).unwrap_or_default(), version))
+}
+
+/// Parse the archived todos of the first list
+pub fn parse_archive(contents: &str) -> Result<Vec<Todo>, SchemaError> {
+    let (list, _) = parse_first_list(contents)?;
+    Ok(list.map(|list| list.archive).unwrap_or_default())
+}
+
+fn parse_first_list(contents: &str) -> Result<(Option<TodoList>, u64), SchemaError> {
+    let value = serde_json::from_str(contents)?;
+    let (value, version) = migrate(value)?;
+    let data: DataFile = serde_json::from_value(value)?;
+    Ok((data.lists.into_iter().next(), version))
+}
+
+/// Build the new contents of todos.json from the existing ones, replacing the
+/// todos of the first list, adding `archived` to its archive and keeping
+/// everything else as it was. The next todo ID stored is at least `next_id`.
+pub fn update(
+    existing: Option<&str>,
+    todos: &[Todo],
+    archived: &[Todo],
+    next_id: u64,
+) -> Result<String, SchemaError> {
+    let mut value = upgrade_existing(existing)?;
+    bump_revision(&mut value);
+    let stored_next_id = next_id_in(&value);
+    let list = first_list(&mut value);
+    list["todos"] = serde_json::to_value(todos)?;
+    if !archived.is_empty() {
+        if !list["archive"].is_array() {
+            list["archive"] = json!([]);
+        }
+        if let Some(archive) = list["archive"].as_array_mut() {
+            for todo in archived {
+                archive.push(serde_json::to_value(todo)?);
+            }
+        }
+    }
+    // IDs of todos that were removed since are never given out again
+    let next_id = [stored_next_id, next_id, next_id_in(&value)]
+        .into_iter()
+        .max()
+        .unwrap_or(1);
+    value["next_id"] = json!(next_id);
+    Ok(serde_json::to_string_pretty(&value)?)
+}
+
+/// Number of times the file has been saved, to notice saves by other programs.
+/// Files from before the counter count as revision 0.
+pub fn revision(contents: &str) -> u64 {
+    serde_json::from_str::<Value>(contents)
+        .ok()
+        .and_then(|value| value["revision"].as_u64())
+        .unwrap_or(0)
+}
+
+/// Lowest todo ID that was never given out. Files from before the counter
+/// have only the IDs of the todos still in them to go by.
+pub fn next_id(contents: &str) -> u64 {
+    serde_json::from_str::<Value>(contents)
+        .ok()
+        .and_then(|value| Some(next_id_in(&migrate(value).ok()?.0)))
+        .unwrap_or(1)
+}
+
+// Helper function to find the lowest ID above the stored counter and the
+// open and archived todos of the first list
+fn next_id_in(value: &Value) -> u64 {
+    let list = &value["lists"][0];
+    let highest = ["todos", "archive"]
+        .iter()
+        .filter_map(|key| list[key].as_array())
+        .flatten()
+        .filter_map(|todo| todo["id"].as_u64())
+        .max()
+        .unwrap_or(0);
+    value["next_id"].as_u64().unwrap_or(1).max(highest + 1)
+}
+
+/// Markdown file the first list is bound to, if any
+pub fn markdown_binding(contents: &str) -> Option<PathBuf> {
+    let value: Value = serde_json::from_str(contents).ok()?;
+    value["lists"][0]["markdown"].as_str().map(PathBuf::from)
+}
+
+/// Bind the first list to a markdown file, or remove the binding
+pub fn set_markdown_binding(
+    existing: Option<&str>,
+    path: Option<&Path>,
+) -> Result<String, SchemaError> {
+    let mut value = upgrade_existing(existing)?;
+    bump_revision(&mut value);
+    let list = first_list(&mut value);
+    match path {
+        Some(path) => list["markdown"] = json!(path),
+        None => {
+            if let Some(list) = list.as_object_mut() {
+                list.remove("markdown");
+            }
+        }
+    }
+    Ok(serde_json::to_string_pretty(&value)?)
+}
+
+// Parse and migrate the existing file, starting over if there is none.
+// A file that can't be parsed is refused, so it is never overwritten.
+fn upgrade_existing(existing: Option<&str>) -> Result<Value, SchemaError> {
+    match existing {
+        Some(contents) => Ok(migrate(serde_json::from_str(contents)?)?.0),
+        None => Ok(empty_file()),
+    }
+}
+
+fn bump_revision(value: &mut Value) {
+    value["revision"] = json!(value["revision"].as_u64().unwrap_or(0) + 1);
+}
+
+fn first_list(value: &mut Value) -> &mut Value {
+    if value["lists"].get(0).is_none() {
+        value["lists"] = json!([{ "name": "Todos", "todos": [] }]);
+    }
+    &mut value["lists"][0]
+}
+
+// Detect the version of a parsed file and run all migrations up to the
+// current version. Refuses files written by a newer version.
+fn migrate(mut value: Value) -> Result<(Value, u64), SchemaError> {
+    let version = match &value {
+        Value::Array(_) => 1, // Before versioning the file was a bare array of todos
+        _ if value["version"].is_null() => 1,
+        _ => match value["version"].as_u64() {
+            Some(version) if version > 0 => version,
+            _ => {
+                return Err(SchemaError::Invalid(serde::de::Error::custom(format!(
+                    "unknown version {}",
+                    value["version"]
+                ))))
+            }
+        },
+    };
+    if version > CURRENT_VERSION {
+        return Err(SchemaError::TooNew(version));
+    }
+
+    for migration in &MIGRATIONS[(version - 1) as usize..] {
+        value = migration(value);
+    }
+    Ok((value, version))
+}
+
+fn empty_file() -> Value {
+    json!({
+        "version": CURRENT_VERSION,
+        "lists": [{ "name": "Todos", "todos": [] }],
+        "settings": {},
+    })
+}
+
+// Version 1 was a bare array of todos; wrap it into the versioned envelope
+fn v1_to_v2(value: Value) -> Value {
+    json!({
+        "version": 2,
+        "lists": [{ "name": "Todos", "todos": value }],
+        "settings": {},
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unknown_versions_are_refused() {
+        for contents in [
+            r#"{"version": 0, "lists": []}"#,
+            r#"{"version": "2", "lists": []}"#,
+        ] {
+            assert!(matches!(parse(contents), Err(SchemaError::Invalid(_))));
+        }
+        assert!(matches!(
+            parse(r#"{"version": 3, "lists": []}"#),
+            Err(SchemaError::TooNew(3))
+        ));
+    }
+
+    #[test]
+    fn unparseable_files_are_not_overwritten() {
+        let broken = r#"{"version": 2, "lists": [{"name": "Todos", "todos": [],}]}"#;
+        assert!(matches!(
+            update(Some(broken), &[Todo::new("new")], &[], 1),
+            Err(SchemaError::Invalid(_))
+        ));
+        assert!(update(None, &[Todo::new("new")], &[], 1).is_ok());
+    }
+
+    #[test]
+    fn ids_of_removed_and_archived_todos_are_not_reused() {
+        let todos: Vec<Todo> = (1..=3)
+            .map(|id| Todo {
+                id,
+                ..Todo::new("todo")
+            })
+            .collect();
+        let contents = update(None, &todos, &[], 1).unwrap();
+        assert_eq!(next_id(&contents), 4);
+
+        // Archive #2 and remove #3
+        let contents = update(Some(&contents), &todos[..1], &todos[1..2], 1).unwrap();
+        assert_eq!(next_id(&contents), 4);
+        let contents = update(Some(&contents), &[], &[], 1).unwrap();
+        assert_eq!(next_id(&contents), 4);
+        assert_eq!(next_id(&update(Some(&contents), &[], &[], 9).unwrap()), 9);
+
+        // Files from before the counter go by the todos in them
+        let old = r#"{"version": 2, "lists": [{"name": "Todos", "todos": [],
+            "archive": [{"id": 7, "name": "old", "completed": true}]}]}"#;
+        assert_eq!(next_id(old), 8);
+    }
+}
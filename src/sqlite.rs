@@ -0,0 +1,520 @@
+// src/sqlite.rs
+
+use crate::{
+    storage::{is_stale, next_free_id, remember_next_id, remember_revision, Storage, StorageError},
+    ProgressEntry, Todo,
+};
+use rusqlite::{params, Connection, OptionalExtension, Transaction, TransactionBehavior};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::Duration,
+};
+
+// Version of the database layout created by this build, kept in user_version
+pub const SCHEMA_VERSION: u32 = 1;
+
+// Todos are rows of their own, so a save only touches the todos that changed.
+// The full todo is kept as JSON in `data`, minus its progress history, which
+// only ever grows and goes to a table of its own. The columns next to it are
+// copies used for ordering and searching.
+const CREATE_TABLES: &str = "
+    CREATE TABLE IF NOT EXISTS meta (
+        key TEXT PRIMARY KEY,
+        value
+    );
+    CREATE TABLE IF NOT EXISTS todos (
+        row INTEGER PRIMARY KEY,
+        id INTEGER NOT NULL,
+        archived INTEGER NOT NULL DEFAULT 0,
+        position INTEGER NOT NULL DEFAULT 0,
+        name TEXT NOT NULL,
+        completed_at TEXT,
+        data TEXT NOT NULL
+    );
+    CREATE UNIQUE INDEX IF NOT EXISTS todos_open_id ON todos (id) WHERE archived = 0;
+    CREATE INDEX IF NOT EXISTS todos_archive ON todos (archived, completed_at);
+    CREATE TABLE IF NOT EXISTS history (
+        todo_row INTEGER NOT NULL REFERENCES todos (row) ON DELETE CASCADE,
+        at TEXT NOT NULL,
+        progress INTEGER NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS history_todo ON history (todo_row, at);
+";
+
+// Todos kept in an embedded SQLite database, todos.sqlite3
+pub struct SqliteStorage {
+    path: PathBuf,
+    connection: Mutex<Connection>,
+}
+
+impl SqliteStorage {
+    // Open the database, creating it if needed. Refuses databases created by
+    // a newer version.
+    pub fn open(path: &Path) -> Result<SqliteStorage, StorageError> {
+        if let Some(data_dir) = path.parent() {
+            fs::create_dir_all(data_dir)?;
+        }
+        let connection = Connection::open(path)?;
+        // Other ratodui processes only hold the database for a moment
+        connection.busy_timeout(Duration::from_secs(5))?;
+        connection.pragma_update(None, "foreign_keys", true)?;
+
+        let version: u32 = connection.pragma_query_value(None, "user_version", |row| row.get(0))?;
+        if version > SCHEMA_VERSION {
+            return Err(StorageError::DatabaseTooNew(version));
+        }
+        connection.execute_batch(CREATE_TABLES)?;
+        connection.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+
+        Ok(SqliteStorage {
+            path: path.to_path_buf(),
+            connection: Mutex::new(connection),
+        })
+    }
+
+    // Run `update` in a write transaction, unless another program saved since
+    // our last read. Returns whether it ran.
+    fn update(
+        &self,
+        update: impl FnOnce(&Transaction) -> Result<(), StorageError>,
+    ) -> Result<bool, StorageError> {
+        let mut connection = self.connection.lock().unwrap();
+        // Take the write lock right away, so nobody saves between the
+        // revision check and our changes
+        let transaction = connection.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        if is_stale(revision(&transaction)?) {
+            return Ok(false);
+        }
+
+        update(&transaction)?;
+        let revision = revision(&transaction)? + 1;
+        set_meta(&transaction, "revision", Some(&revision.to_string()))?;
+        // IDs of todos that were removed since are never given out again
+        let next_id = next_id(&transaction)?.max(next_free_id());
+        set_meta(&transaction, "next_id", Some(&next_id.to_string()))?;
+        transaction.commit()?;
+        remember_revision(revision);
+        remember_next_id(next_id);
+        Ok(true)
+    }
+}
+
+impl Storage for SqliteStorage {
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn read(&self) -> Result<Vec<Todo>, StorageError> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+        remember_revision(revision(&transaction)?);
+        remember_next_id(next_id(&transaction)?);
+        read_rows(
+            &transaction,
+            "SELECT row, data FROM todos WHERE archived = 0 ORDER BY position",
+            [],
+        )
+    }
+
+    fn read_archive(&self, pattern: Option<&str>) -> Result<Vec<Todo>, StorageError> {
+        let connection = self.connection.lock().unwrap();
+        // Escape the wildcards, so the pattern is matched as typed
+        let pattern = pattern.map(|pattern| {
+            let escaped = pattern
+                .replace('\\', "\\\\")
+                .replace('%', "\\%")
+                .replace('_', "\\_");
+            format!("%{}%", escaped)
+        });
+        read_rows(
+            &connection,
+            "SELECT row, data FROM todos
+             WHERE archived = 1 AND (?1 IS NULL OR name LIKE ?1 ESCAPE '\\')
+             ORDER BY completed_at, row",
+            [pattern],
+        )
+    }
+
+    fn write(&self, todos: &[Todo], archived: &[Todo]) -> Result<bool, StorageError> {
+        self.update(|transaction| {
+            // Open todos by ID: their row, stored data and position
+            let mut existing: HashMap<u64, (i64, String, i64)> = HashMap::new();
+            {
+                let mut statement = transaction
+                    .prepare("SELECT id, row, data, position FROM todos WHERE archived = 0")?;
+                let mut rows = statement.query([])?;
+                while let Some(row) = rows.next()? {
+                    let id: i64 = row.get(0)?;
+                    existing.insert(id as u64, (row.get(1)?, row.get(2)?, row.get(3)?));
+                }
+            }
+
+            // Archived todos keep their row, so their history stays with them
+            for todo in archived {
+                let data = todo_data(todo)?;
+                let row = match existing.remove(&todo.id) {
+                    Some((row, _, _)) => {
+                        transaction.execute(
+                            "UPDATE todos SET archived = 1, name = ?2, completed_at = ?3, data = ?4
+                             WHERE row = ?1",
+                            params![row, todo.name, todo.completed_at, data],
+                        )?;
+                        row
+                    }
+                    None => insert_row(transaction, todo, true, 0, &data)?,
+                };
+                write_history(transaction, row, &todo.history)?;
+            }
+
+            for (position, todo) in todos.iter().enumerate() {
+                let position = position as i64;
+                let data = todo_data(todo)?;
+                let row = match existing.remove(&todo.id) {
+                    Some((row, stored, stored_position))
+                        if stored == data && stored_position == position =>
+                    {
+                        row
+                    }
+                    Some((row, _, _)) => {
+                        transaction.execute(
+                            "UPDATE todos SET position = ?2, name = ?3, completed_at = ?4, data = ?5
+                             WHERE row = ?1",
+                            params![row, position, todo.name, todo.completed_at, data],
+                        )?;
+                        row
+                    }
+                    None => insert_row(transaction, todo, false, position, &data)?,
+                };
+                write_history(transaction, row, &todo.history)?;
+            }
+
+            // Whatever is left was removed from the list
+            for (row, _, _) in existing.into_values() {
+                transaction.execute("DELETE FROM todos WHERE row = ?1", [row])?;
+            }
+            Ok(())
+        })
+    }
+
+    fn markdown_binding(&self) -> Option<PathBuf> {
+        let connection = self.connection.lock().unwrap();
+        connection
+            .query_row("SELECT value FROM meta WHERE key = 'markdown'", [], |row| {
+                row.get::<_, String>(0)
+            })
+            .ok()
+            .map(PathBuf::from)
+    }
+
+    fn set_markdown_binding(&self, path: Option<&Path>) -> Result<bool, StorageError> {
+        let path = path.map(|path| path.to_string_lossy().into_owned());
+        self.update(|transaction| set_meta(transaction, "markdown", path.as_deref()))
+    }
+}
+
+// Number of times the database has been saved
+fn revision(connection: &Connection) -> Result<u64, StorageError> {
+    let revision: Option<String> = connection
+        .query_row("SELECT value FROM meta WHERE key = 'revision'", [], |row| {
+            row.get(0)
+        })
+        .optional()?;
+    Ok(revision.and_then(|r| r.parse().ok()).unwrap_or(0))
+}
+
+// Lowest todo ID never given out: above the stored counter and all todos,
+// open or archived
+fn next_id(connection: &Connection) -> Result<u64, StorageError> {
+    let stored: Option<String> = connection
+        .query_row("SELECT value FROM meta WHERE key = 'next_id'", [], |row| {
+            row.get(0)
+        })
+        .optional()?;
+    let highest: Option<i64> =
+        connection.query_row("SELECT MAX(id) FROM todos", [], |row| row.get(0))?;
+    let stored = stored.and_then(|id| id.parse().ok()).unwrap_or(1);
+    Ok(stored.max(highest.unwrap_or(0) as u64 + 1))
+}
+
+// Set a value in the meta table, or remove it with None
+fn set_meta(connection: &Connection, key: &str, value: Option<&str>) -> Result<(), StorageError> {
+    match value {
+        Some(value) => connection.execute(
+            "INSERT INTO meta (key, value) VALUES (?1, ?2)
+             ON CONFLICT (key) DO UPDATE SET value = excluded.value",
+            params![key, value],
+        )?,
+        None => connection.execute("DELETE FROM meta WHERE key = ?1", [key])?,
+    };
+    Ok(())
+}
+
+// Read the todos selected by a query returning their row and data, along
+// with their history
+fn read_rows(
+    connection: &Connection,
+    query: &str,
+    params: impl rusqlite::Params,
+) -> Result<Vec<Todo>, StorageError> {
+    let mut todos = Vec::new();
+    let mut rows = HashMap::new();
+    let mut statement = connection.prepare(query)?;
+    let mut results = statement.query(params)?;
+    while let Some(result) = results.next()? {
+        let data: String = result.get(1)?;
+        rows.insert(result.get::<_, i64>(0)?, todos.len());
+        todos.push(serde_json::from_str::<Todo>(&data)?);
+    }
+
+    let mut statement = connection
+        .prepare("SELECT at, progress FROM history WHERE todo_row = ?1 ORDER BY rowid")?;
+    for (row, i) in rows {
+        let entries = statement.query_map([row], |result| {
+            Ok(ProgressEntry {
+                at: result.get(0)?,
+                progress: result.get(1)?,
+            })
+        })?;
+        for entry in entries {
+            todos[i].history.push(entry?);
+        }
+    }
+    Ok(todos)
+}
+
+fn insert_row(
+    transaction: &Transaction,
+    todo: &Todo,
+    archived: bool,
+    position: i64,
+    data: &str,
+) -> Result<i64, StorageError> {
+    transaction.execute(
+        "INSERT INTO todos (id, archived, position, name, completed_at, data)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![
+            todo.id as i64,
+            archived,
+            position,
+            todo.name,
+            todo.completed_at,
+            data
+        ],
+    )?;
+    Ok(transaction.last_insert_rowid())
+}
+
+// Append the progress entries the database doesn't have yet. History only
+// grows, so anything else means it was replaced and is written anew.
+fn write_history(
+    transaction: &Transaction,
+    row: i64,
+    history: &[ProgressEntry],
+) -> Result<(), StorageError> {
+    let stored: i64 = transaction.query_row(
+        "SELECT COUNT(*) FROM history WHERE todo_row = ?1",
+        [row],
+        |result| result.get(0),
+    )?;
+    let stored = stored as usize;
+    let stored = if stored > history.len() {
+        transaction.execute("DELETE FROM history WHERE todo_row = ?1", [row])?;
+        0
+    } else {
+        stored
+    };
+
+    let mut statement = transaction
+        .prepare_cached("INSERT INTO history (todo_row, at, progress) VALUES (?1, ?2, ?3)")?;
+    for entry in &history[stored..] {
+        statement.execute(params![row, entry.at, entry.progress])?;
+    }
+    Ok(())
+}
+
+// The todo as stored in the data column, without its history
+fn todo_data(todo: &Todo) -> Result<String, StorageError> {
+    let mut value = serde_json::to_value(todo)?;
+    if let Some(fields) = value.as_object_mut() {
+        fields.remove("history");
+    }
+    Ok(serde_json::to_string(&value)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+    use std::{env, sync::PoisonError};
+
+    // The last revision read is kept for the whole process, so the tests
+    // take turns
+    static SERIAL: Mutex<()> = Mutex::new(());
+
+    // A fresh database in the temporary directory, read once so its revision
+    // is the one we know about
+    fn open(name: &str) -> SqliteStorage {
+        let path = env::temp_dir().join(format!("ratodui-{}-{}.sqlite3", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        let storage = SqliteStorage::open(&path).unwrap();
+        storage.read().unwrap();
+        storage
+    }
+
+    // Open todos and archived todos as stored: ID, row, position, archived
+    fn rows(storage: &SqliteStorage) -> Vec<(u64, i64, i64, bool)> {
+        let connection = storage.connection.lock().unwrap();
+        let mut statement = connection
+            .prepare("SELECT id, row, position, archived FROM todos ORDER BY row")
+            .unwrap();
+        let rows = statement
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, i64>(0)? as u64,
+                    row.get(1)?,
+                    row.get(2)?,
+                    row.get(3)?,
+                ))
+            })
+            .unwrap();
+        rows.map(Result::unwrap).collect()
+    }
+
+    fn history_rows(storage: &SqliteStorage) -> Vec<(i64, u16)> {
+        let connection = storage.connection.lock().unwrap();
+        let mut statement = connection
+            .prepare("SELECT rowid, progress FROM history ORDER BY rowid")
+            .unwrap();
+        let rows = statement
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap();
+        rows.map(Result::unwrap).collect()
+    }
+
+    fn todo(id: u64, name: &str) -> Todo {
+        Todo {
+            id,
+            ..Todo::new(name)
+        }
+    }
+
+    fn entry(minute: u32, progress: u16) -> ProgressEntry {
+        ProgressEntry {
+            at: Utc.with_ymd_and_hms(2024, 1, 1, 12, minute, 0).unwrap(),
+            progress,
+        }
+    }
+
+    fn names(todos: &[Todo]) -> Vec<&str> {
+        todos.iter().map(|todo| todo.name.as_str()).collect()
+    }
+
+    #[test]
+    fn todos_keep_their_row_when_moved() {
+        let _serial = SERIAL.lock().unwrap_or_else(PoisonError::into_inner);
+        let storage = open("rows");
+        let (a, b) = (todo(1, "a"), todo(2, "b"));
+        assert!(storage.write(&[a.clone(), b.clone()], &[]).unwrap());
+        let before = rows(&storage);
+        assert_eq!(before.iter().map(|row| row.2).collect::<Vec<_>>(), [0, 1]);
+
+        let renamed = Todo {
+            name: String::from("b2"),
+            ..b
+        };
+        assert!(storage.write(&[renamed, a], &[]).unwrap());
+        let after = rows(&storage);
+        assert_eq!(after[0].1, before[0].1);
+        assert_eq!(after[1].1, before[1].1);
+        assert_eq!(after.iter().map(|row| row.2).collect::<Vec<_>>(), [1, 0]);
+        assert_eq!(names(&storage.read().unwrap()), ["b2", "a"]);
+        let _ = fs::remove_file(storage.path());
+    }
+
+    #[test]
+    fn history_is_appended_unless_replaced() {
+        let _serial = SERIAL.lock().unwrap_or_else(PoisonError::into_inner);
+        let storage = open("history");
+        let mut a = todo(1, "a");
+        a.history = vec![entry(0, 10)];
+        storage.write(&[a.clone()], &[]).unwrap();
+        let first = history_rows(&storage);
+
+        a.history.push(entry(1, 20));
+        storage.write(&[a.clone()], &[]).unwrap();
+        let appended = history_rows(&storage);
+        assert_eq!(appended.len(), 2);
+        assert_eq!(appended[0], first[0]);
+        assert!(storage.read().unwrap()[0].history == a.history);
+
+        // A shorter history was replaced, not appended to
+        a.history = vec![entry(2, 50)];
+        storage.write(&[a.clone()], &[]).unwrap();
+        let replaced = history_rows(&storage);
+        assert_eq!(replaced.len(), 1);
+        assert_eq!(replaced[0].1, 50);
+        assert!(storage.read().unwrap()[0].history == a.history);
+        let _ = fs::remove_file(storage.path());
+    }
+
+    #[test]
+    fn archived_todos_keep_their_row_and_history() {
+        let _serial = SERIAL.lock().unwrap_or_else(PoisonError::into_inner);
+        let storage = open("archive");
+        let mut a = todo(1, "a");
+        a.history = vec![entry(0, 100)];
+        storage.write(&[a.clone(), todo(2, "b")], &[]).unwrap();
+        let before = rows(&storage);
+
+        a.set_completed(true);
+        storage.write(&[todo(2, "b")], &[a.clone()]).unwrap();
+        let after = rows(&storage);
+        assert_eq!(after[0], (1, before[0].1, before[0].2, true));
+        assert_eq!(history_rows(&storage).len(), 1);
+        assert_eq!(names(&storage.read().unwrap()), ["b"]);
+        let archive = storage.read_archive(None).unwrap();
+        assert_eq!(names(&archive), ["a"]);
+        assert!(archive[0].history == a.history);
+        let _ = fs::remove_file(storage.path());
+    }
+
+    #[test]
+    fn saves_over_newer_changes_are_refused() {
+        let _serial = SERIAL.lock().unwrap_or_else(PoisonError::into_inner);
+        let storage = open("stale");
+        assert!(storage.write(&[todo(1, "a")], &[]).unwrap());
+
+        // Another program saves in the meantime
+        let other = Connection::open(storage.path()).unwrap();
+        other
+            .execute_batch(
+                r#"UPDATE todos SET data = json_set(data, '$.name', 'theirs');
+                   UPDATE meta SET value = CAST(value + 1 AS TEXT) WHERE key = 'revision';"#,
+            )
+            .unwrap();
+
+        assert!(!storage.write(&[todo(1, "mine")], &[]).unwrap());
+        assert_eq!(names(&storage.read().unwrap()), ["theirs"]);
+        assert!(storage.write(&[todo(1, "mine")], &[]).unwrap());
+        assert_eq!(names(&storage.read().unwrap()), ["mine"]);
+        let _ = fs::remove_file(storage.path());
+    }
+
+    #[test]
+    fn archive_patterns_are_matched_as_typed() {
+        let _serial = SERIAL.lock().unwrap_or_else(PoisonError::into_inner);
+        let storage = open("pattern");
+        let archived = [todo(1, "100% done"), todo(2, "a_b"), todo(3, "axb")];
+        storage.write(&[], &archived).unwrap();
+
+        let matching = |pattern| storage.read_archive(Some(pattern)).unwrap();
+        assert_eq!(names(&matching("%")), ["100% done"]);
+        assert_eq!(names(&matching("a_b")), ["a_b"]);
+        assert_eq!(names(&matching("X")), ["axb"]);
+        assert_eq!(storage.read_archive(None).unwrap().len(), 3);
+        let _ = fs::remove_file(storage.path());
+    }
+}
@@ -0,0 +1,427 @@
+// src/storage.rs
+
+//! Where the todos are kept: todos.json or an SQLite database.
+
+use crate::{
+    crypto, crypto::CryptoError, lock, schema, schema::SchemaError, sqlite::SqliteStorage, Todo,
+};
+use directories::ProjectDirs;
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        OnceLock,
+    },
+    time::SystemTime,
+};
+
+// Revision of the stored todos as last read or written by this process.
+// Saves are refused when the store has a newer one, i.e. another program
+// saved in the meantime.
+static REVISION: AtomicU64 = AtomicU64::new(0);
+
+// Lowest todo ID never given out, as far as the stored todos read or written
+// by this process tell. New todos get at least this ID, so the IDs of removed
+// and archived todos don't come back.
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+// The storage selected in the config, opened on first use
+static CONFIGURED: OnceLock<Box<dyn Storage + Send + Sync>> = OnceLock::new();
+
+/// Where todos are kept
+pub trait Storage {
+    /// File the todos are stored in; the lock files sit next to it
+    fn path(&self) -> &Path;
+
+    /// Read the open todos
+    fn read(&self) -> Result<Vec<Todo>, StorageError>;
+
+    /// Read the archived todos, optionally only those whose name contains `pattern`
+    fn read_archive(&self, pattern: Option<&str>) -> Result<Vec<Todo>, StorageError>;
+
+    /// Replace the open todos and add `archived` to the archive. Returns false
+    /// without writing anything if another program saved since our last read.
+    fn write(&self, todos: &[Todo], archived: &[Todo]) -> Result<bool, StorageError>;
+
+    /// Markdown file the list is bound to, if any
+    fn markdown_binding(&self) -> Option<PathBuf>;
+
+    /// Bind the list to a markdown file, or remove the binding. Returns false
+    /// like `write` does.
+    fn set_markdown_binding(&self, path: Option<&Path>) -> Result<bool, StorageError>;
+
+    /// Changes whenever the stored todos do, without having to read them
+    fn stamp(&self) -> Option<(SystemTime, u64)> {
+        let metadata = fs::metadata(self.path()).ok()?;
+        Some((metadata.modified().ok()?, metadata.len()))
+    }
+}
+
+/// How the todos are stored in the data directory
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Default, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    #[default]
+    /// todos.json, rewritten as a whole on every save
+    Json,
+    /// todos.sqlite3, updated row by row
+    Sqlite,
+}
+
+impl Backend {
+    /// Find the backend with the name used in the config and on the command
+    /// line, i.e. "json" or "sqlite"
+    pub fn parse(name: &str) -> Option<Backend> {
+        match name {
+            "json" => Some(Backend::Json),
+            "sqlite" => Some(Backend::Sqlite),
+            _ => None,
+        }
+    }
+
+    /// Name of the backend in config.json and on the command line
+    pub fn name(self) -> &'static str {
+        match self {
+            Backend::Json => "json",
+            Backend::Sqlite => "sqlite",
+        }
+    }
+
+    /// Location of the store in the data directory
+    pub fn path(self) -> Option<PathBuf> {
+        let file_name = match self {
+            Backend::Json => "todos.json",
+            Backend::Sqlite => "todos.sqlite3",
+        };
+        ProjectDirs::from("com", "todo", "todo")
+            .map(|proj_dirs| proj_dirs.data_dir().join(file_name))
+    }
+}
+
+/// Why the todos could not be read or saved
+#[derive(Debug)]
+pub enum StorageError {
+    /// No data directory could be found for this user
+    NoDataDir,
+    /// todos.json could not be parsed or written
+    Schema(SchemaError),
+    /// The SQLite database could not be read or updated
+    Database(rusqlite::Error),
+    /// Created by a newer version of ratodui
+    DatabaseTooNew(u32),
+    /// The todos could not be encrypted or decrypted
+    Crypto(CryptoError),
+    /// Reading or writing a file failed
+    Io(io::Error),
+    // The todos were written, but these went wrong after
+    /// Updating the bound markdown file
+    Markdown(PathBuf, io::Error),
+    /// Committing the save
+    Git(String),
+}
+
+impl StorageError {
+    /// Whether the todos were saved despite the error, which is then only
+    /// about keeping the bound markdown file or the git history up to date
+    pub fn todos_saved(&self) -> bool {
+        matches!(self, StorageError::Markdown(..) | StorageError::Git(_))
+    }
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StorageError::NoDataDir => write!(f, "no data directory available"),
+            StorageError::Schema(e) => write!(f, "{}", e),
+            StorageError::Database(e) => write!(f, "database error: {}", e),
+            StorageError::DatabaseTooNew(version) => write!(
+                f,
+                "todos.sqlite3 has version {} but this ratodui only understands up to version {}; \
+                 please upgrade ratodui",
+                version,
+                crate::sqlite::SCHEMA_VERSION
+            ),
+            StorageError::Crypto(e) => write!(f, "{}", e),
+            StorageError::Io(e) => write!(f, "{}", e),
+            StorageError::Markdown(path, e) => {
+                write!(f, "failed to update {}: {}", path.display(), e)
+            }
+            StorageError::Git(e) => write!(f, "failed to commit todos: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for StorageError {}
+
+impl From<SchemaError> for StorageError {
+    fn from(e: SchemaError) -> Self {
+        StorageError::Schema(e)
+    }
+}
+
+impl From<serde_json::Error> for StorageError {
+    fn from(e: serde_json::Error) -> Self {
+        StorageError::Schema(SchemaError::Invalid(e))
+    }
+}
+
+impl From<rusqlite::Error> for StorageError {
+    fn from(e: rusqlite::Error) -> Self {
+        StorageError::Database(e)
+    }
+}
+
+impl From<CryptoError> for StorageError {
+    fn from(e: CryptoError) -> Self {
+        StorageError::Crypto(e)
+    }
+}
+
+impl From<io::Error> for StorageError {
+    fn from(e: io::Error) -> Self {
+        StorageError::Io(e)
+    }
+}
+
+/// Open a storage backend
+pub fn open(backend: Backend) -> Result<Box<dyn Storage + Send + Sync>, StorageError> {
+    let path = backend.path().ok_or(StorageError::NoDataDir)?;
+    match backend {
+        Backend::Json => Ok(Box::new(JsonStorage { path })),
+        Backend::Sqlite => Ok(Box::new(SqliteStorage::open(&path)?)),
+    }
+}
+
+/// The storage selected in the config
+pub fn configured() -> Result<&'static dyn Storage, StorageError> {
+    if let Some(storage) = CONFIGURED.get() {
+        return Ok(storage.as_ref());
+    }
+    let storage = open(crate::config::current().storage)?;
+    Ok(CONFIGURED.get_or_init(|| storage).as_ref())
+}
+
+/// Wait for the update lock of the configured storage, if it can be locked
+pub fn lock_for_update() -> Option<lock::UpdateLock> {
+    configured()
+        .ok()
+        .and_then(|storage| lock::lock_for_update(storage.path()).ok())
+}
+
+/// Remember the revision the todos were read or written at
+pub fn remember_revision(revision: u64) {
+    REVISION.store(revision, Ordering::SeqCst);
+}
+
+/// Whether a stored revision is newer than the one we know about
+pub fn is_stale(revision: u64) -> bool {
+    revision > REVISION.load(Ordering::SeqCst)
+}
+
+/// Remember the lowest todo ID a store says was never given out. The ID only
+/// ever goes up.
+pub fn remember_next_id(next_id: u64) {
+    NEXT_ID.fetch_max(next_id, Ordering::SeqCst);
+}
+
+/// Lowest ID a new todo may get, as far as the stores we have seen tell
+pub fn next_free_id() -> u64 {
+    NEXT_ID.load(Ordering::SeqCst)
+}
+
+/// Contents of todos.json if it is encrypted and no passphrase has been given yet
+pub fn locked_contents() -> Option<String> {
+    let storage = configured().ok()?;
+    if crate::config::current().storage != Backend::Json || crypto::is_unlocked() {
+        return None;
+    }
+    let contents = fs::read_to_string(storage.path()).ok()?;
+    crypto::is_encrypted(&contents).then_some(contents)
+}
+
+/// Encrypt todos.json with a new passphrase, or store it in plain text again
+/// with None. Encrypted todos have to be unlocked first.
+pub fn set_encryption(passphrase: Option<&str>) -> Result<(), StorageError> {
+    let path = Backend::Json.path().ok_or(StorageError::NoDataDir)?;
+    let storage = JsonStorage { path };
+    let _lock = lock::lock_for_update(&storage.path)?;
+    let json = match storage.contents()? {
+        Some(json) => json,
+        None => schema::update(None, &[], &[], next_free_id())?,
+    };
+    crypto::set_passphrase(passphrase)?;
+    storage.write_file(&json)
+}
+
+/// Backups in the data directory, made before a migration. Encrypting
+/// todos.json leaves them in plain text.
+pub fn backups() -> Vec<PathBuf> {
+    let Some(data_dir) = Backend::Json
+        .path()
+        .and_then(|path| path.parent().map(Path::to_path_buf))
+    else {
+        return Vec::new();
+    };
+    let mut backups: Vec<PathBuf> = fs::read_dir(data_dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| path.extension().is_some_and(|extension| extension == "bak"))
+        .collect();
+    backups.sort();
+    backups
+}
+
+/// Copy all todos, the archive and the markdown binding to another backend,
+/// keeping the old store as a .bak file. Returns the number of open and
+/// archived todos copied.
+pub fn migrate(from: Backend, to: Backend) -> Result<(usize, usize), String> {
+    let target_path = to.path().ok_or("no data directory available")?;
+    if target_path.exists() {
+        return Err(format!(
+            "{} already exists; move it out of the way first",
+            target_path.display()
+        ));
+    }
+
+    if to == Backend::Sqlite && crypto::is_unlocked() {
+        return Err(String::from(
+            "the SQLite storage isn't encrypted; run `ratodui decrypt` first",
+        ));
+    }
+
+    let source = open(from).map_err(|e| e.to_string())?;
+    if let Ok(None) = lock::try_lock_instance(source.path()) {
+        return Err(String::from("quit the running ratodui first"));
+    }
+    let _lock = lock::lock_for_update(source.path()).map_err(|e| e.to_string())?;
+
+    let todos = source.read().map_err(|e| e.to_string())?;
+    let archived = source.read_archive(None).map_err(|e| e.to_string())?;
+    let binding = source.markdown_binding();
+
+    let target = open(to).map_err(|e| e.to_string())?;
+    target.write(&todos, &archived).map_err(|e| e.to_string())?;
+    if let Some(binding) = binding {
+        target
+            .set_markdown_binding(Some(&binding))
+            .map_err(|e| e.to_string())?;
+    }
+
+    if source.path().exists() {
+        let backup_path = PathBuf::from(format!("{}.bak", source.path().display()));
+        fs::rename(source.path(), backup_path).map_err(|e| e.to_string())?;
+    }
+    Ok((todos.len(), archived.len()))
+}
+
+// The todos.json file, in the layout described in schema.rs
+struct JsonStorage {
+    path: PathBuf,
+}
+
+impl JsonStorage {
+    // The contents of the file, decrypted if needed
+    fn contents(&self) -> Result<Option<String>, StorageError> {
+        match fs::read_to_string(&self.path) {
+            Ok(contents) => Ok(Some(crypto::open(&contents)?)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    // Write the file, encrypted if a passphrase was given. A temporary file is
+    // moved in place, so readers never see half a file.
+    fn write_file(&self, json: &str) -> Result<(), StorageError> {
+        let sealed = crypto::seal(json.to_string())?;
+        let temp_path = self.path.with_extension("json.tmp");
+        fs::write(&temp_path, sealed)?;
+        fs::rename(&temp_path, &self.path)?;
+        Ok(())
+    }
+
+    // Replace the file with contents built from the current ones, holding the
+    // update lock meanwhile. Returns None without writing if the file has a
+    // newer revision than the one we last read.
+    fn replace(
+        &self,
+        update: impl FnOnce(Option<&str>) -> Result<String, SchemaError>,
+    ) -> Result<Option<String>, StorageError> {
+        if let Some(data_dir) = self.path.parent() {
+            fs::create_dir_all(data_dir)?;
+        }
+        let _lock = lock::lock_for_update(&self.path)?;
+
+        let existing = self.contents()?;
+        if let Some(existing) = &existing {
+            if is_stale(schema::revision(existing)) {
+                return Ok(None);
+            }
+        }
+        let json = update(existing.as_deref())?;
+        self.write_file(&json)?;
+        remember_revision(schema::revision(&json));
+        remember_next_id(schema::next_id(&json));
+        Ok(Some(json))
+    }
+}
+
+impl Storage for JsonStorage {
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn read(&self) -> Result<Vec<Todo>, StorageError> {
+        let Some(contents) = self.contents()? else {
+            return Ok(Vec::new());
+        };
+        remember_revision(schema::revision(&contents));
+        remember_next_id(schema::next_id(&contents));
+        let (todos, version) = schema::parse(&contents)?;
+        if version < schema::CURRENT_VERSION {
+            backup_before_migration(&self.path, version);
+        }
+        Ok(todos)
+    }
+
+    fn read_archive(&self, pattern: Option<&str>) -> Result<Vec<Todo>, StorageError> {
+        let Some(contents) = self.contents()? else {
+            return Ok(Vec::new());
+        };
+        let mut archived = schema::parse_archive(&contents)?;
+        if let Some(pattern) = pattern {
+            let pattern = pattern.to_lowercase();
+            archived.retain(|todo| todo.name.to_lowercase().contains(&pattern));
+        }
+        Ok(archived)
+    }
+
+    fn write(&self, todos: &[Todo], archived: &[Todo]) -> Result<bool, StorageError> {
+        // Other lists and settings in the file are kept, and files written
+        // by a newer version are never overwritten
+        let written =
+            self.replace(|existing| schema::update(existing, todos, archived, next_free_id()))?;
+        Ok(written.is_some())
+    }
+
+    fn markdown_binding(&self) -> Option<PathBuf> {
+        schema::markdown_binding(&self.contents().ok()??)
+    }
+
+    fn set_markdown_binding(&self, path: Option<&Path>) -> Result<bool, StorageError> {
+        let written = self.replace(|existing| schema::set_markdown_binding(existing, path))?;
+        Ok(written.is_some())
+    }
+}
+
+// Keep a copy of a file in an older format before it is upgraded
+fn backup_before_migration(file_path: &Path, version: u64) {
+    let backup_path = file_path.with_file_name(format!("todos.v{}.json.bak", version));
+    if !backup_path.exists() {
+        if let Err(e) = fs::copy(file_path, &backup_path) {
+            eprintln!("Failed to back up todos before migrating: {}", e);
+        }
+    }
+}
@@ -0,0 +1,140 @@
+// src/taskwarrior.rs
+
+use crate::{recurrence::Recurrence, Todo};
+use chrono::{DateTime, Local, NaiveDateTime, Utc};
+use serde_json::Value;
+
+const DATE_TIME_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+
+// Import the output of `task export`, which is a JSON array of tasks or,
+// from older versions, one JSON object per line. Deleted tasks and the
+// templates of recurring tasks are skipped; dependencies become blockers.
+pub fn import(contents: &str) -> Result<Vec<Todo>, String> {
+    let tasks: Vec<Value> = match serde_json::from_str(contents) {
+        Ok(Value::Array(tasks)) => tasks,
+        _ => contents
+            .lines()
+            .map(|line| line.trim().trim_end_matches(','))
+            .filter(|line| !line.is_empty() && *line != "[" && *line != "]")
+            .map(serde_json::from_str)
+            .collect::<Result<_, _>>()
+            .map_err(|e| format!("not a Taskwarrior export: {}", e))?,
+    };
+    let tasks: Vec<&Value> = tasks
+        .iter()
+        .filter(|task| !matches!(task["status"].as_str(), Some("deleted" | "recurring")))
+        .collect();
+
+    // Number the tasks in order so dependencies can refer to them by ID
+    let uuids: Vec<&str> = tasks
+        .iter()
+        .map(|task| task["uuid"].as_str().unwrap_or(""))
+        .collect();
+    let todos = tasks
+        .iter()
+        .enumerate()
+        .map(|(i, task)| {
+            let mut todo = import_task(task, &uuids);
+            todo.id = i as u64 + 1;
+            todo
+        })
+        .collect();
+    Ok(todos)
+}
+
+fn import_task(task: &Value, uuids: &[&str]) -> Todo {
+    let mut todo = Todo::new(task["description"].as_str().unwrap_or("").trim());
+    todo.created_at = parse_date_time(&task["entry"]);
+
+    if task["status"].as_str() == Some("completed") {
+        todo.completed = true;
+        todo.completed_at = parse_date_time(&task["end"]);
+    }
+    todo.priority = match task["priority"].as_str() {
+        Some("H") => Some('A'),
+        Some("M") => Some('B'),
+        Some("L") => Some('C'),
+        _ => None,
+    };
+    // Taskwarrior keeps due dates in UTC; use the local day they fall on
+    todo.due = parse_date_time(&task["due"]).map(|due| due.with_timezone(&Local).date_naive());
+    todo.recurrence = task["recur"].as_str().and_then(parse_recur);
+
+    // Projects nest with dots, e.g. "Home.Garden", and become a +project tag
+    if let Some(project) = task["project"].as_str() {
+        todo.tags.push(format!("+{}", project));
+    }
+    if let Some(tags) = task["tags"].as_array() {
+        for tag in tags.iter().filter_map(|tag| tag.as_str()) {
+            if !todo.tags.iter().any(|existing| existing == tag) {
+                todo.tags.push(tag.to_string());
+            }
+        }
+    }
+
+    // Older versions write dependencies as a comma separated string
+    let depends: Vec<&str> = match &task["depends"] {
+        Value::Array(depends) => depends.iter().filter_map(|uuid| uuid.as_str()).collect(),
+        Value::String(depends) => depends.split(',').collect(),
+        _ => Vec::new(),
+    };
+    todo.blocked_by = depends
+        .iter()
+        .filter_map(|uuid| uuids.iter().position(|other| other == uuid))
+        .map(|i| i as u64 + 1)
+        .collect();
+
+    if let Some(annotations) = task["annotations"].as_array() {
+        let notes: Vec<&str> = annotations
+            .iter()
+            .filter_map(|annotation| annotation["description"].as_str())
+            .collect();
+        todo.notes = notes.join("\n");
+    }
+
+    todo
+}
+
+// Map the recur attribute, e.g. "daily", "weekly", "weekdays" or "3d"
+fn parse_recur(recur: &str) -> Option<Recurrence> {
+    let recurrence = match recur {
+        "daily" | "day" | "1d" => Recurrence::Day,
+        "weekdays" => Recurrence::Weekday,
+        "weekly" | "week" | "1w" | "1wk" => Recurrence::Week { days: Vec::new() },
+        "monthly" | "month" | "1mo" => Recurrence::Month,
+        "biweekly" | "fortnight" => Recurrence::Days { days: 14 },
+        _ => {
+            let count = recur.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+            let days: u32 = count.parse().ok()?;
+            match &recur[count.len()..] {
+                "d" | "days" => Recurrence::Days { days },
+                "w" | "wk" | "wks" | "weeks" => Recurrence::Days {
+                    days: days.checked_mul(7)?,
+                },
+                _ => return None,
+            }
+        }
+    };
+    Some(recurrence)
+}
+
+fn parse_date_time(value: &Value) -> Option<DateTime<Utc>> {
+    let date_time = NaiveDateTime::parse_from_str(value.as_str()?, DATE_TIME_FORMAT).ok()?;
+    Some(date_time.and_utc())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recurrences_too_long_to_count_are_left_out() {
+        assert_eq!(parse_recur("3wks"), Some(Recurrence::Days { days: 21 }));
+        assert_eq!(parse_recur("1000000000w"), None);
+        let todos = import(
+            r#"[{"uuid": "a", "description": "Far", "status": "pending", "recur": "1000000000w"}]"#,
+        )
+        .unwrap();
+        assert_eq!(todos[0].recurrence, None);
+    }
+}
@@ -0,0 +1,491 @@
+// src/todotxt.rs
+
+use crate::{recurrence::Recurrence, ProgressEntry, Todo};
+use chrono::{DateTime, NaiveDate, SecondsFormat, Utc, Weekday};
+
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+// Export todos in the todo.txt format, one todo per line.
+// Fields todo.txt has no syntax for are written as key:value extensions, so
+// importing the result gives back the same todos.
+pub fn export(todos: &[Todo]) -> String {
+    let mut out = String::new();
+    for todo in todos {
+        out.push_str(&export_line(todo));
+        out.push('\n');
+    }
+    out
+}
+
+// Import todos from the todo.txt format, skipping blank lines
+pub fn import(contents: &str) -> Vec<Todo> {
+    contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(import_line)
+        .collect()
+}
+
+fn export_line(todo: &Todo) -> String {
+    let mut parts: Vec<String> = Vec::new();
+
+    if todo.completed {
+        parts.push(String::from("x"));
+        if let Some(completed_at) = todo.completed_at {
+            parts.push(completed_at.date_naive().format(DATE_FORMAT).to_string());
+        }
+    } else if let Some(priority) = todo.priority {
+        parts.push(format!("({})", priority));
+    }
+    // Without a completion date, a creation date would be read as one
+    let created_in_place = !todo.completed || todo.completed_at.is_some();
+    if let (true, Some(created_at)) = (created_in_place, todo.created_at) {
+        parts.push(created_at.date_naive().format(DATE_FORMAT).to_string());
+    }
+
+    if !todo.name.is_empty() {
+        parts.push(encode_name(todo));
+    }
+
+    // Tags that don't already appear in the name; ones without a '+' or '@'
+    // aren't todo.txt tags, so they are extensions
+    for tag in &todo.tags {
+        if !is_tag(tag) {
+            parts.push(format!("tag:{}", encode_value(tag)));
+        } else if !todo.name.split_whitespace().any(|word| word == tag) {
+            parts.push(tag.clone());
+        }
+    }
+
+    // Completed todos keep their priority as an extension, as is customary
+    if let (true, Some(priority)) = (todo.completed, todo.priority) {
+        parts.push(format!("pri:{}", priority));
+    }
+    if let Some(due) = todo.due {
+        parts.push(format!("due:{}", due.format(DATE_FORMAT)));
+    }
+    if let Some(recurrence) = &todo.recurrence {
+        parts.push(format!("rec:{}", encode_recurrence(recurrence)));
+    }
+    if todo.progress > 0 {
+        parts.push(format!("progress:{}", todo.progress));
+    }
+    if todo.id != 0 {
+        parts.push(format!("id:{}", todo.id));
+    }
+    if !todo.blocked_by.is_empty() {
+        let ids: Vec<String> = todo.blocked_by.iter().map(|id| id.to_string()).collect();
+        parts.push(format!("blocked:{}", ids.join(",")));
+    }
+    if !todo.notes.is_empty() {
+        parts.push(format!("note:{}", encode_value(&todo.notes)));
+    }
+    if !todo.history.is_empty() {
+        let entries: Vec<String> = todo
+            .history
+            .iter()
+            .map(|entry| format!("{}@{}", entry.progress, encode_time(entry.at)))
+            .collect();
+        parts.push(format!("history:{}", entries.join(",")));
+    }
+
+    // The dates written above lose the time of day
+    if let Some(created_at) = todo.created_at {
+        if !created_in_place || !is_midnight(created_at) {
+            parts.push(format!("created:{}", encode_time(created_at)));
+        }
+    }
+    if let Some(completed_at) = todo.completed_at {
+        if !todo.completed || !is_midnight(completed_at) {
+            parts.push(format!("completed:{}", encode_time(completed_at)));
+        }
+    }
+
+    parts.join(" ")
+}
+
+// Write the name as it is, except for what the import would read as
+// something else: words that look like extensions, tags the todo doesn't
+// have, and a first word that looks like a completion mark, priority or
+// date. Those get a character percent-encoded, as does whitespace other
+// than the spaces between words.
+fn encode_name(todo: &Todo) -> String {
+    let name = &todo.name;
+    let mut encoded = String::new();
+    let chars: Vec<char> = name.chars().collect();
+    let (start, end) = (
+        chars.iter().take_while(|c| **c == ' ').count(),
+        chars.len() - chars.iter().rev().take_while(|c| **c == ' ').count(),
+    );
+    for (i, c) in chars.iter().enumerate() {
+        let decodes = chars
+            .get(i + 1..i + 3)
+            .is_some_and(|hex| hex.iter().all(|c| c.is_ascii_hexdigit()));
+        if (*c == '%' && decodes) || (c.is_whitespace() && (*c != ' ' || i < start || i >= end)) {
+            encoded.push_str(&encode_char(*c));
+        } else {
+            encoded.push(*c);
+        }
+    }
+
+    let words: Vec<String> = encoded
+        .split(' ')
+        .enumerate()
+        .map(|(i, word)| {
+            let clashes = if i == 0 {
+                word == "x" || parse_priority(word).is_some() || parse_date(word).is_some()
+            } else {
+                false
+            };
+            if clashes || (is_tag(word) && !todo.tags.iter().any(|tag| tag == word)) {
+                let first = word.chars().next().unwrap_or_default();
+                format!("{}{}", encode_char(first), &word[first.len_utf8()..])
+            } else if is_extension(word) {
+                word.replacen(':', "%3A", 1)
+            } else {
+                word.to_string()
+            }
+        })
+        .collect();
+    words.join(" ")
+}
+
+// Helper function to tell whether a word is a project or context tag
+fn is_tag(word: &str) -> bool {
+    (word.starts_with('+') || word.starts_with('@')) && word.len() > 1
+}
+
+// Helper function to tell whether the import reads a word as an extension
+fn is_extension(word: &str) -> bool {
+    word.split_once(':')
+        .is_some_and(|(key, value)| import_extension(&mut Todo::new(""), key, value))
+}
+
+fn is_midnight(time: DateTime<Utc>) -> bool {
+    time.date_naive()
+        .and_hms_opt(0, 0, 0)
+        .map(|midnight| midnight.and_utc())
+        == Some(time)
+}
+
+fn encode_time(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::AutoSi, true)
+}
+
+fn decode_time(value: &str) -> Option<DateTime<Utc>> {
+    Some(
+        DateTime::parse_from_rfc3339(value)
+            .ok()?
+            .with_timezone(&Utc),
+    )
+}
+
+fn import_line(line: &str) -> Todo {
+    let mut todo = Todo::new("");
+    todo.created_at = None;
+    let mut words = split_words(line).into_iter().peekable();
+
+    if words.peek().is_some_and(|(word, _)| *word == "x") {
+        words.next();
+        todo.completed = true;
+        if let Some(date) = words.peek().and_then(|(word, _)| parse_date(word)) {
+            words.next();
+            todo.completed_at = Some(date);
+        }
+    } else if let Some(priority) = words.peek().and_then(|(word, _)| parse_priority(word)) {
+        words.next();
+        todo.priority = Some(priority);
+    }
+    if let Some(date) = words.peek().and_then(|(word, _)| parse_date(word)) {
+        words.next();
+        todo.created_at = Some(date);
+    }
+
+    // The name keeps the spacing between its words
+    let mut name = String::new();
+    for (word, spacing) in words {
+        if let Some((key, value)) = word.split_once(':') {
+            if import_extension(&mut todo, key, value) {
+                continue;
+            }
+        }
+        if is_tag(word) && !todo.tags.iter().any(|tag| tag == word) {
+            todo.tags.push(word.to_string());
+        }
+        if !name.is_empty() {
+            name.push_str(spacing);
+        }
+        name.push_str(&decode_value(word));
+    }
+    todo.name = name;
+
+    todo
+}
+
+// Helper function to split a line into its words, each with the whitespace
+// before it
+fn split_words(line: &str) -> Vec<(&str, &str)> {
+    let mut words = Vec::new();
+    let mut rest = line;
+    while !rest.is_empty() {
+        let word_start = rest.len() - rest.trim_start().len();
+        let word_end = rest[word_start..]
+            .find(char::is_whitespace)
+            .map_or(rest.len(), |end| word_start + end);
+        if word_start < word_end {
+            words.push((&rest[word_start..word_end], &rest[..word_start]));
+        }
+        rest = &rest[word_end..];
+    }
+    words
+}
+
+// Apply a known key:value extension, returning false for unknown keys so
+// they stay part of the name
+fn import_extension(todo: &mut Todo, key: &str, value: &str) -> bool {
+    match key {
+        "pri" => match parse_priority(&format!("({})", value)) {
+            Some(priority) => todo.priority = Some(priority),
+            None => return false,
+        },
+        "due" => match NaiveDate::parse_from_str(value, DATE_FORMAT) {
+            Ok(due) => todo.due = Some(due),
+            Err(_) => return false,
+        },
+        "rec" => match decode_recurrence(value) {
+            Some(recurrence) => todo.recurrence = Some(recurrence),
+            None => return false,
+        },
+        "progress" => match value.parse::<u16>() {
+            Ok(progress) if progress <= 100 => todo.progress = progress,
+            _ => return false,
+        },
+        "id" => match value.parse() {
+            Ok(id) => todo.id = id,
+            Err(_) => return false,
+        },
+        "blocked" => {
+            todo.blocked_by = value.split(',').filter_map(|id| id.parse().ok()).collect();
+        }
+        "note" => todo.notes = decode_value(value),
+        "tag" => todo.tags.push(decode_value(value)),
+        "history" => {
+            let entries = value
+                .split(',')
+                .map(|entry| {
+                    let (progress, at) = entry.split_once('@')?;
+                    Some(ProgressEntry {
+                        at: decode_time(at)?,
+                        progress: progress.parse().ok().filter(|progress| *progress <= 100)?,
+                    })
+                })
+                .collect::<Option<Vec<_>>>();
+            match entries {
+                Some(entries) => todo.history = entries,
+                None => return false,
+            }
+        }
+        "created" => match decode_time(value) {
+            Some(created_at) => todo.created_at = Some(created_at),
+            None => return false,
+        },
+        "completed" => match decode_time(value) {
+            Some(completed_at) => todo.completed_at = Some(completed_at),
+            None => return false,
+        },
+        _ => return false,
+    }
+    true
+}
+
+fn parse_date(word: &str) -> Option<DateTime<Utc>> {
+    let date = NaiveDate::parse_from_str(word, DATE_FORMAT).ok()?;
+    Some(date.and_hms_opt(0, 0, 0)?.and_utc())
+}
+
+fn parse_priority(word: &str) -> Option<char> {
+    let mut chars = word.chars();
+    match (chars.next(), chars.next(), chars.next(), chars.next()) {
+        (Some('('), Some(priority), Some(')'), None) if priority.is_ascii_uppercase() => {
+            Some(priority)
+        }
+        _ => None,
+    }
+}
+
+// Recurrences use the rec: convention of other todo.txt tools, where a
+// leading '+' means the schedule follows the due date rather than the
+// completion date: "+1d", "+1b" (weekdays), "+1w", "+1m", "+3d" or "7d".
+// Weekly rules on given days are written as "+mon,thu".
+fn encode_recurrence(recurrence: &Recurrence) -> String {
+    match recurrence {
+        Recurrence::Day => String::from("+1d"),
+        Recurrence::Weekday => String::from("+1b"),
+        Recurrence::Week { days } if days.is_empty() => String::from("+1w"),
+        Recurrence::Week { days } => {
+            let days: Vec<String> = days
+                .iter()
+                .map(|day| day.to_string().to_lowercase())
+                .collect();
+            format!("+{}", days.join(","))
+        }
+        Recurrence::Month => String::from("+1m"),
+        Recurrence::Days { days } => format!("+{}d", days),
+        Recurrence::AfterCompletion { days } => format!("{}d", days),
+    }
+}
+
+fn decode_recurrence(value: &str) -> Option<Recurrence> {
+    let (strict, rule) = match value.strip_prefix('+') {
+        Some(rule) => (true, rule),
+        None => (false, value),
+    };
+
+    let unit = rule.chars().last()?;
+    if let Ok(count) = rule[..rule.len() - unit.len_utf8()].parse::<u32>() {
+        let count = count.max(1);
+        let recurrence = match (strict, unit) {
+            (true, 'd') if count == 1 => Recurrence::Day,
+            (true, 'd') => Recurrence::Days { days: count },
+            (true, 'b') => Recurrence::Weekday,
+            (true, 'w') if count == 1 => Recurrence::Week { days: Vec::new() },
+            (true, 'w') => Recurrence::Days {
+                days: count.checked_mul(7)?,
+            },
+            (true, 'm') => Recurrence::Month,
+            (false, 'd' | 'b') => Recurrence::AfterCompletion { days: count },
+            (false, 'w') => Recurrence::AfterCompletion {
+                days: count.checked_mul(7)?,
+            },
+            (false, 'm') => Recurrence::AfterCompletion {
+                days: count.checked_mul(30)?,
+            },
+            _ => return None,
+        };
+        return Some(recurrence);
+    }
+
+    let days = rule
+        .split(',')
+        .map(|day| day.parse::<Weekday>().ok())
+        .collect::<Option<Vec<_>>>()?;
+    Some(Recurrence::Week { days })
+}
+
+// Values can't contain whitespace, so it is percent-encoded along with '%'
+fn encode_value(value: &str) -> String {
+    let mut encoded = String::new();
+    for c in value.chars() {
+        if c == '%' || c.is_whitespace() {
+            encoded.push_str(&encode_char(c));
+        } else {
+            encoded.push(c);
+        }
+    }
+    encoded
+}
+
+fn encode_char(c: char) -> String {
+    let mut buffer = [0; 4];
+    c.encode_utf8(&mut buffer)
+        .bytes()
+        .map(|byte| format!("%{:02X}", byte))
+        .collect()
+}
+
+fn decode_value(value: &str) -> String {
+    let mut bytes = Vec::new();
+    let mut rest = value.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let hex = tail.get(..2).and_then(|hex| std::str::from_utf8(hex).ok());
+        match hex.and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+            Some(decoded) if byte == b'%' => {
+                bytes.push(decoded);
+                rest = &tail[2..];
+            }
+            _ => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recurrences_too_long_to_count_are_left_out() {
+        assert_eq!(
+            decode_recurrence("+3w"),
+            Some(Recurrence::Days { days: 21 })
+        );
+        assert_eq!(decode_recurrence("+1000000000w"), None);
+        assert_eq!(decode_recurrence("1000000000m"), None);
+        let todo = import_line("Pay rent rec:1000000000m");
+        assert_eq!(todo.recurrence, None);
+        assert_eq!(todo.name, "Pay rent rec:1000000000m");
+    }
+
+    // Helper function to export a todo and import it again
+    fn round_trip(todo: &Todo) -> Todo {
+        let line = export_line(todo);
+        import_line(&line)
+    }
+
+    #[test]
+    fn names_survive_a_round_trip() {
+        for name in [
+            "id:7 weird",
+            "Call  mum   back",
+            "x marks the spot",
+            "(A) is a grade",
+            "2024-01-01 was a Monday",
+            "100%25 sure",
+            " padded\tname ",
+            "Email @bob about due:friday",
+        ] {
+            let mut todo = Todo::new(name);
+            todo.created_at = None;
+            assert_eq!(round_trip(&todo).name, name);
+        }
+    }
+
+    #[test]
+    fn every_field_survives_a_round_trip() {
+        let at = |s: &str| DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc);
+        let mut todo = Todo::new("Write +report for @work");
+        todo.id = 7;
+        todo.progress = 60;
+        todo.completed = true;
+        todo.history = vec![
+            ProgressEntry {
+                at: at("2024-03-01T09:30:00Z"),
+                progress: 20,
+            },
+            ProgressEntry {
+                at: at("2024-03-02T17:05:12.250Z"),
+                progress: 60,
+            },
+        ];
+        todo.notes = String::from("Ask about the\nnumbers, 100%");
+        todo.due = NaiveDate::from_ymd_opt(2024, 3, 8);
+        todo.recurrence = Some(Recurrence::Days { days: 14 });
+        todo.blocked_by = vec![3, 4];
+        todo.priority = Some('B');
+        todo.tags = vec![
+            String::from("+report"),
+            String::from("@work"),
+            String::from("urgent"),
+        ];
+        todo.created_at = Some(at("2024-02-28T08:00:00Z"));
+        todo.completed_at = Some(at("2024-03-02T17:05:12Z"));
+        assert!(round_trip(&todo) == todo, "{}", export_line(&todo));
+
+        // A completed todo with a creation date but no completion date
+        todo.completed_at = None;
+        assert!(round_trip(&todo) == todo, "{}", export_line(&todo));
+    }
+}